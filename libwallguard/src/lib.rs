@@ -11,44 +11,77 @@ use tokio::sync::mpsc;
 use tonic::Request;
 pub use tonic::Streaming;
 use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
-use tonic::transport::Channel;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
 
 mod proto;
 
+/// TLS settings used to connect to a server that serves the control service over TLS.
+#[derive(Clone, Debug, Default)]
+pub struct TlsOptions {
+    /// PEM-encoded CA certificate used to verify the server. The system roots are used if `None`.
+    pub ca_certificate: Option<Vec<u8>>,
+    /// PEM-encoded certificate and private key presented to servers requiring client authentication.
+    pub client_identity: Option<(Vec<u8>, Vec<u8>)>,
+    /// Name expected in the server certificate, if it differs from the address connected to.
+    pub domain_name: Option<String>,
+}
+
+impl TlsOptions {
+    fn into_config(self) -> ClientTlsConfig {
+        let mut config = ClientTlsConfig::new();
+
+        config = match self.ca_certificate {
+            Some(ca) => config.ca_certificate(Certificate::from_pem(ca)),
+            None => config.with_native_roots(),
+        };
+
+        if let Some((cert, key)) = self.client_identity {
+            config = config.identity(Identity::from_pem(cert, key));
+        }
+
+        if let Some(domain_name) = self.domain_name {
+            config = config.domain_name(domain_name);
+        }
+
+        config
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct WallGuardGrpcInterface {
     client: WallGuardClient<Channel>,
 }
 
 impl WallGuardGrpcInterface {
-    #[allow(clippy::missing_panics_doc)]
     pub async fn new(addr: &str, port: u16) -> Result<Self, Error> {
-        let addr = format!("http://{addr}:{port}");
-
-        let channel = Channel::from_shared(addr)
-            .expect("Failed to parse address")
-            .timeout(Duration::from_secs(10))
-            .keep_alive_timeout(Duration::from_secs(10))
-            .connect()
-            .await
-            .handle_err(location!())?;
+        Self::connect(format!("http://{addr}:{port}"), None).await
+    }
 
-        let client = WallGuardClient::new(channel).max_decoding_message_size(50 * 1024 * 1024);
+    pub async fn from_sockaddr(addr: SocketAddr) -> Result<Self, Error> {
+        Self::connect(format!("http://{addr}"), None).await
+    }
 
-        Ok(Self { client })
+    /// Connects to a server that serves the control service over TLS.
+    pub async fn new_with_tls(addr: &str, port: u16, tls: TlsOptions) -> Result<Self, Error> {
+        Self::connect(format!("https://{addr}:{port}"), Some(tls.into_config())).await
     }
 
-    #[allow(clippy::missing_panics_doc)]
-    pub async fn from_sockaddr(addr: SocketAddr) -> Result<Self, Error> {
-        let addr = format!("http://{addr}");
+    /// Same as `new_with_tls`, connecting to a socket address.
+    pub async fn from_sockaddr_with_tls(addr: SocketAddr, tls: TlsOptions) -> Result<Self, Error> {
+        Self::connect(format!("https://{addr}"), Some(tls.into_config())).await
+    }
 
-        let channel = Channel::from_shared(addr)
-            .expect("Failed to parse address")
+    async fn connect(addr: String, tls: Option<ClientTlsConfig>) -> Result<Self, Error> {
+        let mut endpoint = Channel::from_shared(addr)
+            .handle_err(location!())?
             .timeout(Duration::from_secs(10))
-            .keep_alive_timeout(Duration::from_secs(10))
-            .connect()
-            .await
-            .handle_err(location!())?;
+            .keep_alive_timeout(Duration::from_secs(10));
+
+        if let Some(tls) = tls {
+            endpoint = endpoint.tls_config(tls).handle_err(location!())?;
+        }
+
+        let channel = endpoint.connect().await.handle_err(location!())?;
 
         let client = WallGuardClient::new(channel).max_decoding_message_size(50 * 1024 * 1024);
 
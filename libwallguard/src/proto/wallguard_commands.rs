@@ -25,8 +25,17 @@ pub struct Authentication {
     pub app_secret: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CommandResult {
+    #[prost(string, tag = "1")]
+    pub request_id: ::prost::alloc::string::String,
+    #[prost(bool, tag = "2")]
+    pub success: bool,
+    #[prost(string, tag = "3")]
+    pub error: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ClientMessage {
-    #[prost(oneof = "client_message::Message", tags = "1, 2")]
+    #[prost(oneof = "client_message::Message", tags = "1, 2, 4, 5")]
     pub message: ::core::option::Option<client_message::Message>,
 }
 /// Nested message and enum types in `ClientMessage`.
@@ -37,6 +46,10 @@ pub mod client_message {
         AuthorizationRequest(super::AuthorizationRequest),
         #[prost(message, tag = "2")]
         Authentication(super::Authentication),
+        #[prost(message, tag = "4")]
+        CommandResult(super::CommandResult),
+        #[prost(message, tag = "5")]
+        HeartbeatAck(()),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -45,6 +58,12 @@ pub struct AuthenticationData {
     pub app_id: ::core::option::Option<::prost::alloc::string::String>,
     #[prost(string, optional, tag = "2")]
     pub app_secret: ::core::option::Option<::prost::alloc::string::String>,
+    /// PEM-encoded client certificate for mTLS
+    #[prost(string, optional, tag = "3")]
+    pub client_certificate: ::core::option::Option<::prost::alloc::string::String>,
+    /// PEM-encoded private key of the client certificate
+    #[prost(string, optional, tag = "4")]
+    pub client_key: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SshSessionData {
@@ -52,6 +71,9 @@ pub struct SshSessionData {
     pub tunnel_token: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub public_key: ::prost::alloc::string::String,
+    /// Local user the public key must be authorized for
+    #[prost(string, tag = "3")]
+    pub login_user: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct UiSessionData {
@@ -59,12 +81,45 @@ pub struct UiSessionData {
     pub tunnel_token: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub protocol: ::prost::alloc::string::String,
+    /// Port of the web UI, 0 for the protocol default
+    #[prost(uint32, tag = "3")]
+    pub port: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TtyResizeData {
+    /// Token of the tunnel carrying the TTY session
+    #[prost(string, tag = "1")]
+    pub tunnel_token: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub cols: u32,
+    #[prost(uint32, tag = "3")]
+    pub rows: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ConfigurationData {
+    #[prost(string, tag = "1")]
+    pub configuration_id: ::prost::alloc::string::String,
+    /// JSON-encoded array of firewall rules
+    #[prost(string, tag = "2")]
+    pub rules: ::prost::alloc::string::String,
+    /// JSON-encoded array of aliases
+    #[prost(string, tag = "3")]
+    pub aliases: ::prost::alloc::string::String,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ServerShutdownData {
+    /// Delay before the client should reconnect
+    #[prost(uint32, tag = "1")]
+    pub reconnect_after_secs: u32,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ServerMessage {
+    /// Correlates the command with the `CommandResult` sent back by the client.
+    #[prost(string, tag = "100")]
+    pub request_id: ::prost::alloc::string::String,
     #[prost(
         oneof = "server_message::Message",
-        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11"
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14"
     )]
     pub message: ::core::option::Option<server_message::Message>,
 }
@@ -94,5 +149,11 @@ pub mod server_message {
         DeviceDeauthorizedMessage(()),
         #[prost(message, tag = "11")]
         AuthorizationRejectedMessage(()),
+        #[prost(message, tag = "12")]
+        ApplyConfigurationCommand(super::ConfigurationData),
+        #[prost(message, tag = "13")]
+        ServerShutdownMessage(super::ServerShutdownData),
+        #[prost(message, tag = "14")]
+        ResizeTtyCommand(super::TtyResizeData),
     }
 }
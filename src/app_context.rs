@@ -1,8 +1,11 @@
 use nullnet_liberror::Error;
 
+use crate::control_service::{ControlServiceConfig, DeviceCertificateAuthority};
 use crate::datastore::Datastore;
-use crate::orchestrator::Orchestrator;
+use crate::orchestrator::{self, Orchestrator};
 use crate::reverse_tunnel::ReverseTunnel;
+use crate::session_recording::SessionRecorder;
+use crate::shutdown::ShutdownSignal;
 use crate::token_provider::TokenProvider;
 
 // Unfortunately, we have to use both root and system device credentials because:
@@ -42,6 +45,9 @@ pub struct AppContext {
     pub datastore: Datastore,
     pub orchestractor: Orchestrator,
     pub tunnel: ReverseTunnel,
+    pub shutdown: ShutdownSignal,
+    pub device_ca: Option<DeviceCertificateAuthority>,
+    pub recorder: Option<SessionRecorder>,
 
     pub root_token_provider: TokenProvider,
     pub sysdev_token_provider: TokenProvider,
@@ -51,7 +57,9 @@ impl AppContext {
     pub async fn new() -> Result<Self, Error> {
         let datastore = Datastore::new().await?;
         let orchestractor = Orchestrator::new();
-        let tunnel = ReverseTunnel::new();
+        let shutdown = ShutdownSignal::default();
+        let tunnel = ReverseTunnel::new(shutdown.clone());
+        let device_ca = DeviceCertificateAuthority::from_env(&ControlServiceConfig::from_env())?;
 
         let sysdev_token_provider = TokenProvider::new(
             SYSTEM_ACCOUNT_ID.to_string(),
@@ -67,12 +75,25 @@ impl AppContext {
             datastore.clone(),
         );
 
-        Ok(Self {
+        let context = Self {
             datastore,
             orchestractor,
             tunnel,
+            shutdown,
+            device_ca,
+            recorder: SessionRecorder::from_env(),
             sysdev_token_provider,
             root_token_provider,
-        })
+        };
+
+        // No client can be connected yet, so any device still flagged
+        // as online is a leftover from a previous run.
+        if let Err(err) = orchestrator::reset_online_status(&context).await {
+            log::error!("Failed to reset device online status: {}", err.to_str());
+        }
+
+        tokio::spawn(orchestrator::online_status_sweep(context.clone()));
+
+        Ok(context)
     }
 }
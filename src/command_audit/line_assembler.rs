@@ -0,0 +1,449 @@
+//! Reconstruction of the command lines entered in a remote shell from the raw keystrokes.
+//!
+//! The assembler mimics the line editing of common shells (readline, zle, ...):
+//! - printable characters are inserted at the cursor, which moves with the arrow keys,
+//!   `Home`/`End` and their `Ctrl` equivalents,
+//! - `Backspace`, `Delete`, `Ctrl-U`, `Ctrl-K` and `Ctrl-W` delete as usual,
+//! - `Up`/`Down` browse the commands entered earlier in the session,
+//! - bracketed pastes are inserted verbatim, newlines included.
+//!
+//! What the shell does on its own, like tab completion or recalling commands entered
+//! before the session, can't be followed: such lines are flagged as approximate.
+//!
+//! Keystrokes sent while a full-screen program (e.g. `vi`, `top`) is running are not
+//! command lines; the device output is watched for the alternate screen to skip them.
+//! Lines typed at a password prompt are redacted.
+
+/// A command line, complete once the user pressed `Enter`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembledLine {
+    pub command: String,
+    /// Whether the line was edited in ways that can't be followed exactly.
+    pub approximate: bool,
+    /// Whether the line was typed at a password prompt, in which case `command` is empty.
+    pub redacted: bool,
+}
+
+/// Output sequences switching the terminal to and from the alternate screen.
+const ALTERNATE_SCREEN_ENTER: [&str; 3] = ["\x1b[?1049h", "\x1b[?1047h", "\x1b[?47h"];
+const ALTERNATE_SCREEN_EXIT: [&str; 3] = ["\x1b[?1049l", "\x1b[?1047l", "\x1b[?47l"];
+
+/// Number of output characters kept to recognize prompts and split escape sequences.
+const OUTPUT_TAIL_LEN: usize = 256;
+
+#[derive(Debug, Default)]
+enum Escape {
+    #[default]
+    None,
+    /// `ESC` was received.
+    Started,
+    /// Control sequence, `ESC [` followed by its parameters so far.
+    Csi(String),
+    /// Single shift, `ESC O`, sent by the cursor keys in application mode.
+    Ss3,
+}
+
+#[derive(Debug, Default)]
+pub struct LineAssembler {
+    line: Vec<char>,
+    cursor: usize,
+    approximate: bool,
+    escape: Escape,
+    pasting: bool,
+    history: Vec<String>,
+    /// Position in `history` while browsing it.
+    history_index: Option<usize>,
+    full_screen: bool,
+    /// End of the device output, since the last line break.
+    output_tail: String,
+}
+
+impl LineAssembler {
+    /// Processes terminal input, returning the lines it completes.
+    pub fn input(&mut self, text: &str) -> Vec<AssembledLine> {
+        let mut lines = Vec::new();
+
+        for c in text.chars() {
+            if let Some(line) = self.input_char(c) {
+                lines.push(line);
+            }
+        }
+
+        lines
+    }
+
+    /// Processes terminal output, to follow full-screen programs and prompts.
+    pub fn output(&mut self, text: &str) {
+        self.output_tail.push_str(text);
+
+        let enter = last_position(&self.output_tail, &ALTERNATE_SCREEN_ENTER);
+        let exit = last_position(&self.output_tail, &ALTERNATE_SCREEN_EXIT);
+
+        match (enter, exit) {
+            (Some(enter), Some(exit)) => self.set_full_screen(enter > exit),
+            (Some(_), None) => self.set_full_screen(true),
+            (None, Some(_)) => self.set_full_screen(false),
+            (None, None) => {}
+        }
+
+        if let Some(index) = self.output_tail.rfind(['\n', '\r']) {
+            self.output_tail.drain(..=index);
+        }
+
+        let excess = self
+            .output_tail
+            .chars()
+            .count()
+            .saturating_sub(OUTPUT_TAIL_LEN);
+        if excess > 0 {
+            let index = self
+                .output_tail
+                .char_indices()
+                .nth(excess)
+                .map_or(self.output_tail.len(), |(index, _)| index);
+            self.output_tail.drain(..index);
+        }
+    }
+
+    fn set_full_screen(&mut self, full_screen: bool) {
+        if self.full_screen != full_screen {
+            self.full_screen = full_screen;
+            self.reset_line();
+        }
+
+        // The sequences have been handled, they must not be matched again.
+        self.output_tail.clear();
+    }
+
+    fn input_char(&mut self, c: char) -> Option<AssembledLine> {
+        match std::mem::take(&mut self.escape) {
+            Escape::None => {}
+            Escape::Started => {
+                self.escape_char(c);
+                return None;
+            }
+            Escape::Csi(mut params) => {
+                if ('\x40'..='\x7e').contains(&c) {
+                    self.control_sequence(&params, c);
+                } else {
+                    params.push(c);
+                    self.escape = Escape::Csi(params);
+                }
+                return None;
+            }
+            Escape::Ss3 => {
+                self.control_sequence("", c);
+                return None;
+            }
+        }
+
+        if self.full_screen {
+            if c == '\x1b' {
+                self.escape = Escape::Started;
+            }
+            return None;
+        }
+
+        if self.pasting {
+            match c {
+                '\x1b' => self.escape = Escape::Started,
+                '\r' | '\n' => self.insert('\n'),
+                c if c == '\t' || !c.is_control() => self.insert(c),
+                _ => {}
+            }
+            return None;
+        }
+
+        match c {
+            '\r' | '\n' => return self.complete_line(),
+            '\x1b' => self.escape = Escape::Started,
+            '\x7f' | '\x08' => self.backspace(),
+            '\x03' => self.reset_line(),
+            '\x04' => self.delete(),
+            '\x15' => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            '\x0b' => self.line.truncate(self.cursor),
+            '\x17' => self.delete_word(),
+            '\x01' => self.cursor = 0,
+            '\x05' => self.cursor = self.line.len(),
+            '\x02' => self.cursor = self.cursor.saturating_sub(1),
+            '\x06' => self.cursor = (self.cursor + 1).min(self.line.len()),
+            '\x10' => self.history_previous(),
+            '\x0e' => self.history_next(),
+            // Completion and history search, whose results only the shell knows.
+            '\t' | '\x12' => self.approximate = true,
+            c if c.is_control() => {}
+            c => self.insert(c),
+        }
+
+        None
+    }
+
+    fn escape_char(&mut self, c: char) {
+        match c {
+            '[' => self.escape = Escape::Csi(String::new()),
+            'O' => self.escape = Escape::Ss3,
+            // `Alt-b` and `Alt-f` move by word.
+            'b' if !self.full_screen => self.cursor = self.previous_word_start(),
+            'f' if !self.full_screen => self.cursor = self.next_word_end(),
+            _ => {}
+        }
+    }
+
+    fn control_sequence(&mut self, params: &str, action: char) {
+        // Bracketed paste markers are recognized even in full-screen programs,
+        // which can enable bracketed paste as well.
+        if action == '~' && (params == "200" || params == "201") {
+            self.pasting = params == "200" && !self.full_screen;
+            return;
+        }
+
+        if self.full_screen {
+            return;
+        }
+
+        // Modifiers, e.g. `1;5C` for `Ctrl-Right`.
+        let mut params = params.split(';');
+        let key = params.next().unwrap_or_default();
+        let by_word = params.next().is_some_and(|modifier| modifier != "1");
+
+        match (key, action) {
+            (_, 'A') => self.history_previous(),
+            (_, 'B') => self.history_next(),
+            (_, 'C') if by_word => self.cursor = self.next_word_end(),
+            (_, 'D') if by_word => self.cursor = self.previous_word_start(),
+            (_, 'C') => self.cursor = (self.cursor + 1).min(self.line.len()),
+            (_, 'D') => self.cursor = self.cursor.saturating_sub(1),
+            (_, 'H') | ("1" | "7", '~') => self.cursor = 0,
+            (_, 'F') | ("4" | "8", '~') => self.cursor = self.line.len(),
+            ("3", '~') => self.delete(),
+            _ => {}
+        }
+    }
+
+    fn insert(&mut self, c: char) {
+        self.line.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.line.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.line.len() {
+            self.line.remove(self.cursor);
+        }
+    }
+
+    fn delete_word(&mut self) {
+        let start = self.previous_word_start();
+        self.line.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    fn previous_word_start(&self) -> usize {
+        let mut index = self.cursor;
+
+        while index > 0 && self.line[index - 1].is_whitespace() {
+            index -= 1;
+        }
+        while index > 0 && !self.line[index - 1].is_whitespace() {
+            index -= 1;
+        }
+
+        index
+    }
+
+    fn next_word_end(&self) -> usize {
+        let mut index = self.cursor;
+
+        while index < self.line.len() && self.line[index].is_whitespace() {
+            index += 1;
+        }
+        while index < self.line.len() && !self.line[index].is_whitespace() {
+            index += 1;
+        }
+
+        index
+    }
+
+    fn history_previous(&mut self) {
+        let index = self.history_index.unwrap_or(self.history.len());
+
+        if index == 0 {
+            // Beyond the commands of this session, the shell recalls older ones.
+            self.approximate = true;
+            return;
+        }
+
+        self.recall(index - 1);
+    }
+
+    fn history_next(&mut self) {
+        match self.history_index {
+            Some(index) if index + 1 < self.history.len() => self.recall(index + 1),
+            Some(_) => {
+                self.line.clear();
+                self.cursor = 0;
+                self.history_index = None;
+            }
+            None => {}
+        }
+    }
+
+    fn recall(&mut self, index: usize) {
+        self.line = self.history[index].chars().collect();
+        self.cursor = self.line.len();
+        self.history_index = Some(index);
+    }
+
+    fn reset_line(&mut self) {
+        self.line.clear();
+        self.cursor = 0;
+        self.approximate = false;
+        self.pasting = false;
+        self.history_index = None;
+    }
+
+    fn complete_line(&mut self) -> Option<AssembledLine> {
+        let command: String = self.line.iter().collect();
+        let approximate = self.approximate;
+        let redacted = is_password_prompt(&self.output_tail);
+
+        self.reset_line();
+        self.output_tail.clear();
+
+        if redacted {
+            return Some(AssembledLine {
+                command: String::new(),
+                approximate,
+                redacted,
+            });
+        }
+
+        if command.trim().is_empty() && !approximate {
+            return None;
+        }
+
+        if !command.trim().is_empty() {
+            self.history.push(command.clone());
+        }
+
+        Some(AssembledLine {
+            command,
+            approximate,
+            redacted,
+        })
+    }
+}
+
+fn last_position(text: &str, patterns: &[&str]) -> Option<usize> {
+    patterns
+        .iter()
+        .filter_map(|pattern| text.rfind(pattern))
+        .max()
+}
+
+/// Returns `true` if the last line of output asks for a secret.
+fn is_password_prompt(output: &str) -> bool {
+    let prompt = output.trim_end().to_lowercase();
+
+    (prompt.contains("password") || prompt.contains("passphrase")) && prompt.ends_with(':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(assembler: &mut LineAssembler, input: &str) -> Vec<String> {
+        assembler
+            .input(input)
+            .into_iter()
+            .map(|line| line.command)
+            .collect()
+    }
+
+    #[test]
+    fn assembles_edited_lines() {
+        let mut assembler = LineAssembler::default();
+
+        assert_eq!(commands(&mut assembler, "ls -lx\x7fa\r"), ["ls -la"]);
+        assert_eq!(
+            commands(&mut assembler, "cat fle\x1b[D\x1b[Di\r"),
+            ["cat file"]
+        );
+        assert_eq!(commands(&mut assembler, "rm -rf /\x15pwd\r"), ["pwd"]);
+        assert_eq!(commands(&mut assembler, "echo a b\x17c\r"), ["echo a c"]);
+        assert_eq!(commands(&mut assembler, "oops\x03\r"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn recalls_history() {
+        let mut assembler = LineAssembler::default();
+
+        commands(&mut assembler, "uptime\r");
+        commands(&mut assembler, "df -h\r");
+
+        assert_eq!(commands(&mut assembler, "\x1b[A\x1b[A\r"), ["uptime"]);
+        assert_eq!(commands(&mut assembler, "\x1bOA -h\r"), ["uptime -h"]);
+
+        let lines = assembler.input("\x1b[A\x1b[A\x1b[A\x1b[A\x1b[A\r");
+        assert!(lines[0].approximate);
+    }
+
+    #[test]
+    fn inserts_pastes_verbatim() {
+        let mut assembler = LineAssembler::default();
+
+        assert_eq!(
+            commands(
+                &mut assembler,
+                "\x1b[200~for i in 1 2\rdo echo $i\x1b[201~; done\r"
+            ),
+            ["for i in 1 2\ndo echo $i; done"]
+        );
+    }
+
+    #[test]
+    fn flags_completion() {
+        let mut assembler = LineAssembler::default();
+
+        let lines = assembler.input("cat /etc/pas\t\r");
+        assert_eq!(lines[0].command, "cat /etc/pas");
+        assert!(lines[0].approximate);
+    }
+
+    #[test]
+    fn skips_full_screen_programs() {
+        let mut assembler = LineAssembler::default();
+
+        commands(&mut assembler, "vi notes\r");
+        assembler.output("\x1b[?1049h\x1b[H");
+        assert!(commands(&mut assembler, "ihello\r\x1b:wq\r").is_empty());
+
+        assembler.output("\x1b[?1049l$ ");
+        assert_eq!(commands(&mut assembler, "ls\r"), ["ls"]);
+    }
+
+    #[test]
+    fn redacts_passwords() {
+        let mut assembler = LineAssembler::default();
+
+        assembler.output("$ sudo -i\r\n[sudo] password for admin: ");
+        let lines = assembler.input("hunter2\r");
+
+        assert!(lines[0].redacted);
+        assert!(lines[0].command.is_empty());
+
+        // The password is not part of the history.
+        let lines = assembler.input("\x1b[A\r");
+        assert!(lines[0].command.is_empty());
+        assert!(lines[0].approximate);
+    }
+}
@@ -0,0 +1,110 @@
+//! Audit trail of the commands entered in remote shells.
+//!
+//! The keystrokes sent through the SSH and TTY gateways are assembled into command lines,
+//! see [`line_assembler`], and each line is stored in the datastore along with the session,
+//! device and user it belongs to.
+
+use crate::app_context::AppContext;
+use crate::datastore::{RemoteAccessSession, RemoteShellCommand};
+use crate::utilities::utf8::Utf8Decoder;
+use actix_web::web::Data;
+use chrono::Utc;
+use line_assembler::{AssembledLine, LineAssembler};
+use std::sync::{Arc, Mutex};
+
+mod line_assembler;
+
+#[derive(Debug, Default)]
+struct AuditState {
+    assembler: LineAssembler,
+    input: Utf8Decoder,
+    output: Utf8Decoder,
+}
+
+/// Audits the commands entered during a single session. Clones audit into the same trail.
+#[derive(Clone)]
+pub struct CommandAuditor {
+    context: Data<AppContext>,
+    device_id: String,
+    session_id: String,
+    session_type: String,
+    user_id: Option<String>,
+    recording_id: Option<String>,
+    state: Arc<Mutex<AuditState>>,
+}
+
+impl CommandAuditor {
+    pub fn new(
+        context: Data<AppContext>,
+        session: &RemoteAccessSession,
+        recording_id: Option<String>,
+    ) -> Self {
+        Self {
+            context,
+            device_id: session.device_id.clone(),
+            session_id: session.id.clone(),
+            session_type: session.r#type.as_str().to_owned(),
+            user_id: session.owner.clone(),
+            recording_id,
+            state: Arc::default(),
+        }
+    }
+
+    /// Processes data typed by the user, storing the command lines it completes.
+    pub fn input(&self, data: &[u8]) {
+        let lines = {
+            let mut state = self.state.lock().unwrap();
+            let text = state.input.decode(data);
+            state.assembler.input(&text)
+        };
+
+        for line in lines {
+            self.store(line);
+        }
+    }
+
+    /// Processes data sent by the device to the terminal.
+    pub fn output(&self, data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        let text = state.output.decode(data);
+        state.assembler.output(&text);
+    }
+
+    fn store(&self, line: AssembledLine) {
+        let command = RemoteShellCommand {
+            device_id: self.device_id.clone(),
+            remote_access_session_id: self.session_id.clone(),
+            session_type: self.session_type.clone(),
+            user_id: self.user_id.clone(),
+            recording_id: self.recording_id.clone(),
+            command: line.command,
+            approximate: line.approximate,
+            redacted: line.redacted,
+            entered_at: Utc::now().to_rfc3339(),
+        };
+
+        let context = self.context.clone();
+
+        tokio::spawn(async move {
+            let token = match context.sysdev_token_provider.get().await {
+                Ok(token) => token,
+                Err(err) => {
+                    log::error!("Failed to audit shell command: {}", err.to_str());
+                    return;
+                }
+            };
+
+            if let Err(err) = context
+                .datastore
+                .create_shell_command(&token.jwt, &command)
+                .await
+            {
+                log::error!(
+                    "Failed to audit shell command on device {}: {}",
+                    command.device_id,
+                    err.to_str()
+                );
+            }
+        });
+    }
+}
@@ -1,30 +1,87 @@
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
 
 pub struct ControlServiceConfig {
     pub(crate) addr: SocketAddr,
+    pub(crate) tls: Option<ControlServiceTlsConfig>,
+}
+
+/// Locations of the PEM files used to serve the control service over TLS.
+pub struct ControlServiceTlsConfig {
+    pub(crate) cert: PathBuf,
+    pub(crate) key: PathBuf,
+    /// CA used to verify client certificates. Client authentication is disabled if missing.
+    ///
+    /// Client certificates are optional at the TLS level, since devices only receive theirs
+    /// once authorized. Binding a certificate to a device is enforced per request instead.
+    pub(crate) client_ca: Option<PathBuf>,
 }
 
 impl Default for ControlServiceConfig {
     fn default() -> Self {
         let addr = SocketAddr::from_str("127.0.0.1:50051").unwrap();
-        ControlServiceConfig { addr }
+        ControlServiceConfig { addr, tls: None }
     }
 }
 
 impl ControlServiceConfig {
+    /// Constructs a `ControlServiceConfig` from the environment variables
+    /// `CONTROL_SERVICE_ADDR` and `CONTROL_SERVICE_PORT`, falling back to the default address
+    /// if either is missing or invalid.
+    ///
+    /// TLS is enabled when both `CONTROL_SERVICE_TLS_CERT` and `CONTROL_SERVICE_TLS_KEY` are set,
+    /// and client certificates are verified against `CONTROL_SERVICE_TLS_CLIENT_CA` if set.
     pub fn from_env() -> Self {
+        let default = Self::default();
+
         let host = std::env::var("CONTROL_SERVICE_ADDR").ok();
         let port = std::env::var("CONTROL_SERVICE_PORT")
             .ok()
             .and_then(|p| p.parse::<u16>().ok());
 
-        if let (Some(host), Some(port)) = (host, port) {
-            if let Ok(addr) = format!("{}:{}", host, port).parse::<SocketAddr>() {
-                return Self { addr };
-            }
+        let addr = match (host, port) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port)
+                .parse::<SocketAddr>()
+                .unwrap_or(default.addr),
+            _ => default.addr,
+        };
+
+        let cert = std::env::var("CONTROL_SERVICE_TLS_CERT").ok();
+        let key = std::env::var("CONTROL_SERVICE_TLS_KEY").ok();
+
+        let tls = match (cert, key) {
+            (Some(cert), Some(key)) => Some(ControlServiceTlsConfig {
+                cert: cert.into(),
+                key: key.into(),
+                client_ca: std::env::var("CONTROL_SERVICE_TLS_CLIENT_CA")
+                    .ok()
+                    .map(PathBuf::from),
+            }),
+            _ => None,
+        };
+
+        Self { addr, tls }
+    }
+}
+
+impl ControlServiceTlsConfig {
+    /// Reads the configured certificates and builds the corresponding `ServerTlsConfig`.
+    pub fn load(&self) -> Result<ServerTlsConfig, Error> {
+        let cert = std::fs::read(&self.cert).handle_err(location!())?;
+        let key = std::fs::read(&self.key).handle_err(location!())?;
+
+        let mut config = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+
+        if let Some(client_ca) = &self.client_ca {
+            let client_ca = std::fs::read(client_ca).handle_err(location!())?;
+            config = config
+                .client_ca_root(Certificate::from_pem(client_ca))
+                .client_auth_optional(true);
         }
 
-        Self::default()
+        Ok(config)
     }
 }
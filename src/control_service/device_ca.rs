@@ -0,0 +1,70 @@
+use crate::control_service::ControlServiceConfig;
+use crate::utilities::certificates;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use std::path::PathBuf;
+
+const CERTIFICATE_VALIDITY_DAYS: u32 = 365;
+
+/// Client certificate issued to a device at authorization time.
+#[derive(Debug, Clone)]
+pub struct DeviceCertificate {
+    pub certificate: String,
+    pub private_key: String,
+    pub fingerprint: String,
+}
+
+/// CA issuing the client certificates devices use to authenticate to the control service.
+///
+/// Its certificate is the same CA the control service verifies client certificates against.
+#[derive(Debug, Clone)]
+pub struct DeviceCertificateAuthority {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+impl DeviceCertificateAuthority {
+    /// Constructs a `DeviceCertificateAuthority` from the environment variable
+    /// `CONTROL_SERVICE_TLS_CLIENT_CA_KEY` and the client CA of the control service.
+    ///
+    /// Returns `None` if the key is missing, in which case no device certificates are issued.
+    /// Fails if the key is set but the control service does not verify client certificates,
+    /// since certificates issued to devices could then never be checked.
+    pub fn from_env(config: &ControlServiceConfig) -> Result<Option<Self>, Error> {
+        let Ok(key) = std::env::var("CONTROL_SERVICE_TLS_CLIENT_CA_KEY") else {
+            return Ok(None);
+        };
+
+        let cert = config
+            .tls
+            .as_ref()
+            .and_then(|tls| tls.client_ca.clone())
+            .ok_or(
+                "CONTROL_SERVICE_TLS_CLIENT_CA_KEY requires control service TLS with a client CA",
+            )
+            .handle_err(location!())?;
+
+        Ok(Some(Self {
+            cert,
+            key: key.into(),
+        }))
+    }
+
+    /// Issues a client certificate for the device with the given id.
+    pub async fn issue(&self, device_id: &str) -> Result<DeviceCertificate, Error> {
+        let (certificate, private_key) = certificates::issue_client_certificate(
+            &self.cert,
+            &self.key,
+            device_id,
+            CERTIFICATE_VALIDITY_DAYS,
+        )
+        .await?;
+
+        let fingerprint = certificates::pem_fingerprint(&certificate)?;
+
+        Ok(DeviceCertificate {
+            certificate,
+            private_key,
+            fingerprint,
+        })
+    }
+}
@@ -1,11 +1,19 @@
+use crate::utilities::certificates;
 use crate::{control_service::service::WallGuardService, datastore::Device};
 use nullnet_liberror::{Error, ErrorHandler, Location, location};
 use nullnet_libtoken::Token;
+use std::sync::Arc;
+use tonic::transport::CertificateDer;
 
 impl WallGuardService {
+    /// Ensures the device the token was issued to exists and is authorized.
+    ///
+    /// If a client certificate was issued to the device, the request must also have been
+    /// made over a TLS connection authenticated with that very certificate.
     pub(crate) async fn ensure_device_exists_and_authrorized(
         &self,
         token: &Token,
+        peer_certs: Option<Arc<Vec<CertificateDer<'static>>>>,
     ) -> Result<Device, Error> {
         let device = token
             .account
@@ -26,6 +34,20 @@ impl WallGuardService {
             return Err("Device is not authrozied").handle_err(location!());
         }
 
+        if let Some(expected) = &device.certificate_fingerprint {
+            let presented = peer_certs
+                .as_ref()
+                .and_then(|certs| certs.first())
+                .map(|cert| certificates::fingerprint(cert.as_ref()))
+                .ok_or("Device did not present its client certificate")
+                .handle_err(location!())?;
+
+            if &presented != expected {
+                return Err("Client certificate does not belong to the device")
+                    .handle_err(location!());
+            }
+        }
+
         Ok(device)
     }
 }
@@ -1,10 +1,12 @@
 mod config;
+mod device_ca;
 mod ensure_device_exists_and_authrorized;
 mod rpc;
 mod service;
 
 use crate::app_context::AppContext;
-use config::ControlServiceConfig;
+pub use config::ControlServiceConfig;
+pub use device_ca::DeviceCertificateAuthority;
 use service::WallGuardService;
 
 /// Starts the control service.
@@ -15,7 +17,27 @@ use service::WallGuardService;
 pub async fn run_control_service(context: AppContext) {
     let config = ControlServiceConfig::from_env();
     log::info!("Control Service running on {}", config.addr);
-    if let Err(e) = WallGuardService::new(context).serve(config.addr).await {
+
+    if config.tls.is_none() {
+        log::warn!("Control service TLS is not configured, serving in cleartext");
+    }
+
+    let tls = match config.tls.as_ref().map(|tls| tls.load()).transpose() {
+        Ok(tls) => tls,
+        Err(e) => {
+            log::error!(
+                "Failed to load control service TLS configuration: {}",
+                e.to_str()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let shutdown = context.shutdown.clone();
+    if let Err(e) = WallGuardService::new(context)
+        .serve(config.addr, tls, shutdown.triggered())
+        .await
+    {
         log::error!("Control service failed: {}", e.to_str());
         std::process::exit(1);
     }
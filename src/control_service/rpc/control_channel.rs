@@ -13,9 +13,14 @@ impl WallGuardService {
     ) -> Result<Response<<WallGuardService as WallGuard>::ControlChannelStream>, Status> {
         let (sender, receiver) = mpsc::channel(64);
 
+        let remote_addr = request.remote_addr();
+        let peer_certs = request.peer_certs();
+
         self.context.orchestractor.on_new_connection(
             request.into_inner(),
             sender,
+            remote_addr,
+            peer_certs,
             self.context.clone(),
         );
 
@@ -9,11 +9,13 @@ impl WallGuardService {
         &self,
         request: Request<DeviceSettingsRequest>,
     ) -> Result<Response<DeviceSettingsResponse>, Status> {
+        let peer_certs = request.peer_certs();
+
         let token = Token::from_jwt(&request.into_inner().token)
             .map_err(|_| Status::internal("Malformed JWT token"))?;
 
         let device = self
-            .ensure_device_exists_and_authrorized(&token)
+            .ensure_device_exists_and_authrorized(&token, peer_certs)
             .await
             .map_err(|err| Status::internal(err.to_str()))?;
 
@@ -7,21 +7,20 @@ use nullnet_liberror::Error;
 use nullnet_libtoken::Token;
 use tonic::{Request, Response, Status};
 
-// @TODO
-// Save & Update records "status": Active, Draft
-
 impl WallGuardService {
     pub(crate) async fn handle_config_data_impl(
         &self,
         request: Request<ConfigSnapshot>,
     ) -> Result<Response<()>, Status> {
+        let peer_certs = request.peer_certs();
+
         let request = request.into_inner();
 
         let token =
             Token::from_jwt(&request.token).map_err(|_| Status::internal("Malformed JWT token"))?;
 
         let _ = self
-            .ensure_device_exists_and_authrorized(&token)
+            .ensure_device_exists_and_authrorized(&token, peer_certs)
             .await
             .map_err(|err| Status::internal(err.to_str()))?;
 
@@ -10,13 +10,15 @@ impl WallGuardService {
         &self,
         request: Request<SystemResourcesData>,
     ) -> Result<Response<()>, Status> {
+        let peer_certs = request.peer_certs();
+
         let data = request.into_inner();
 
         let token =
             Token::from_jwt(&data.token).map_err(|_| Status::internal("Malformed JWT token"))?;
 
         let _ = self
-            .ensure_device_exists_and_authrorized(&token)
+            .ensure_device_exists_and_authrorized(&token, peer_certs)
             .await
             .map_err(|err| Status::internal(err.to_str()))?;
 
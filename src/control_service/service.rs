@@ -9,7 +9,7 @@ use nullnet_liberror::{Error, ErrorHandler, Location, location};
 use std::net::{IpAddr, SocketAddr};
 use std::sync::mpsc;
 use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
-use tonic::transport::Server;
+use tonic::transport::{Server, ServerTlsConfig};
 use tonic::{Request, Response, Status, Streaming};
 
 // @TODO: Configure through ENV
@@ -37,10 +37,24 @@ impl WallGuardService {
         }
     }
 
-    pub async fn serve(self, addr: SocketAddr) -> Result<(), Error> {
-        Server::builder()
+    /// Serves the control service until `signal` resolves, over TLS if `tls` is provided.
+    ///
+    /// Once `signal` resolves, no new connections are accepted, while the established ones are kept.
+    pub async fn serve(
+        self,
+        addr: SocketAddr,
+        tls: Option<ServerTlsConfig>,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), Error> {
+        let mut server = Server::builder();
+
+        if let Some(tls) = tls {
+            server = server.tls_config(tls).handle_err(location!())?;
+        }
+
+        server
             .add_service(WallGuardServer::new(self))
-            .serve(addr)
+            .serve_with_shutdown(addr, signal)
             .await
             .handle_err(location!())?;
 
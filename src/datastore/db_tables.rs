@@ -4,6 +4,8 @@ pub enum DBTable {
     Devices,
     SSHKeys,
     RemoteAccessSessions,
+    RemoteAccessRecordings,
+    RemoteShellCommands,
     Accounts,
     IpInfos,
     Connections,
@@ -23,6 +25,8 @@ impl Display for DBTable {
             DBTable::Devices => "devices",
             DBTable::SSHKeys => "device_ssh_keys",
             DBTable::RemoteAccessSessions => "device_remote_access_sessions",
+            DBTable::RemoteAccessRecordings => "device_remote_access_recordings",
+            DBTable::RemoteShellCommands => "device_remote_shell_commands",
             DBTable::Accounts => "accounts",
             DBTable::IpInfos => "ip_infos",
             DBTable::Connections => "connections",
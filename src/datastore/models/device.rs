@@ -1,6 +1,9 @@
 use crate::datastore::db_tables::DBTable;
 use serde::{Deserialize, Serialize};
 
+/// User the SSH gateway logs in as on devices without a configured login user.
+pub const DEFAULT_SSH_LOGIN_USER: &str = "root";
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Device {
     pub id: String,
@@ -27,6 +30,60 @@ pub struct Device {
     pub online: bool,
     #[serde(rename = "organization_id")]
     pub organization: String,
+    /// SHA-256 fingerprint of the client certificate issued to the device, if any.
+    #[serde(rename = "client_certificate_fingerprint", default)]
+    pub certificate_fingerprint: Option<String>,
+    #[serde(rename = "ui_protocol", default)]
+    pub ui_protocol: Option<UiProtocol>,
+    #[serde(rename = "ui_port", default)]
+    pub ui_port: Option<u16>,
+    /// `Host` header sent to the web UI.
+    #[serde(rename = "ui_host", default)]
+    pub ui_host: Option<String>,
+    /// Server name presented during the TLS handshake, defaults to the `Host` header.
+    #[serde(rename = "ui_tls_server_name", default)]
+    pub ui_tls_server_name: Option<String>,
+    /// SHA-256 fingerprint of the web UI certificate, pinned on first connection.
+    #[serde(rename = "ui_tls_certificate_fingerprint", default)]
+    pub ui_tls_certificate_fingerprint: Option<String>,
+    /// User the SSH gateway logs in as, defaults to `DEFAULT_SSH_LOGIN_USER`.
+    #[serde(rename = "ssh_login_user", default)]
+    pub ssh_login_user: Option<String>,
+}
+
+/// Protocol served by the web UI of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UiProtocol {
+    #[default]
+    Http,
+    Https,
+}
+
+impl UiProtocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UiProtocol::Http => "http",
+            UiProtocol::Https => "https",
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            UiProtocol::Http => 80,
+            UiProtocol::Https => 443,
+        }
+    }
+}
+
+/// How the web UI of a device is reached, with defaults applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceUiSettings {
+    pub protocol: UiProtocol,
+    pub port: u16,
+    pub host: String,
+    pub tls_server_name: String,
+    pub certificate_fingerprint: Option<String>,
 }
 
 impl Device {
@@ -44,10 +101,100 @@ impl Device {
             "device_name".into(),
             "is_device_online".into(),
             "organization_id".into(),
+            "client_certificate_fingerprint".into(),
+            "ui_protocol".into(),
+            "ui_port".into(),
+            "ui_host".into(),
+            "ui_tls_server_name".into(),
+            "ui_tls_certificate_fingerprint".into(),
+            "ssh_login_user".into(),
         ]
     }
 
+    pub fn ui_settings(&self) -> DeviceUiSettings {
+        let protocol = self.ui_protocol.unwrap_or_default();
+        let port = self.ui_port.unwrap_or(protocol.default_port());
+
+        let host = match &self.ui_host {
+            Some(host) => host.clone(),
+            None if port == protocol.default_port() => "localhost".into(),
+            None => format!("localhost:{port}"),
+        };
+
+        let tls_server_name = match &self.ui_tls_server_name {
+            Some(server_name) => server_name.clone(),
+            None => strip_port(&host).into(),
+        };
+
+        DeviceUiSettings {
+            protocol,
+            port,
+            host,
+            tls_server_name,
+            certificate_fingerprint: self.ui_tls_certificate_fingerprint.clone(),
+        }
+    }
+
+    pub fn ssh_login_user(&self) -> &str {
+        self.ssh_login_user
+            .as_deref()
+            .unwrap_or(DEFAULT_SSH_LOGIN_USER)
+    }
+
     pub fn table() -> DBTable {
         DBTable::Devices
     }
 }
+
+/// Removes the port, if any, from a `Host` header value.
+fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        // IPv6 literal, e.g. `[::1]:8443`
+        return host
+            .split_once(']')
+            .map_or(host, |(address, _)| &address[1..]);
+    }
+
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ui_settings_defaults() {
+        let settings = Device::default().ui_settings();
+
+        assert_eq!(settings.protocol, UiProtocol::Http);
+        assert_eq!(settings.port, 80);
+        assert_eq!(settings.host, "localhost");
+        assert_eq!(settings.tls_server_name, "localhost");
+        assert_eq!(settings.certificate_fingerprint, None);
+    }
+
+    #[test]
+    fn ui_settings_non_default_port() {
+        let device = Device {
+            ui_protocol: Some(UiProtocol::Https),
+            ui_port: Some(8443),
+            ..Default::default()
+        };
+
+        let settings = device.ui_settings();
+
+        assert_eq!(settings.host, "localhost:8443");
+        assert_eq!(settings.tls_server_name, "localhost");
+    }
+
+    #[test]
+    fn server_name_from_host() {
+        assert_eq!(strip_port("fw.local:4443"), "fw.local");
+        assert_eq!(strip_port("fw.local"), "fw.local");
+        assert_eq!(strip_port("[::1]:443"), "::1");
+        assert_eq!(strip_port("[::1]"), "::1");
+    }
+}
@@ -2,6 +2,18 @@ use serde::{Deserialize, Serialize};
 
 use crate::datastore::db_tables::DBTable;
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigurationStatus {
+    /// Desired configuration submitted through the API, not yet confirmed by the device.
+    Draft,
+    /// Configuration that is known to be running on the device.
+    #[default]
+    Applied,
+    /// Configuration the device failed to apply, see `DeviceConfiguration::error`.
+    Failed,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct DeviceConfiguration {
     pub id: String,
@@ -11,6 +23,11 @@ pub struct DeviceConfiguration {
     pub raw_content: String,
     #[serde(rename = "config_version")]
     pub version: i32,
+    #[serde(rename = "config_status", default)]
+    pub status: ConfigurationStatus,
+    /// Error reported by the device when it failed to apply the configuration.
+    #[serde(rename = "config_error", default)]
+    pub error: Option<String>,
 }
 
 impl DeviceConfiguration {
@@ -22,6 +39,8 @@ impl DeviceConfiguration {
             "device_id".into(),
             "raw_content".into(),
             "config_version".into(),
+            "config_status".into(),
+            "config_error".into(),
         ]
     }
 
@@ -1,11 +1,15 @@
 mod device;
 mod device_configuration;
 mod installation_code;
+mod remote_access_recording;
 mod remote_access_session;
+mod remote_shell_command;
 mod ssh_keypair;
 
 pub use device::*;
 pub use device_configuration::*;
 pub use installation_code::*;
+pub use remote_access_recording::*;
 pub use remote_access_session::*;
+pub use remote_shell_command::*;
 pub use ssh_keypair::*;
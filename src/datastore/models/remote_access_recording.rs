@@ -0,0 +1,33 @@
+use crate::datastore::db_tables::DBTable;
+use serde::{Deserialize, Serialize};
+
+/// A recording of an SSH or TTY session, listed so that it can be found for playback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteAccessRecording {
+    pub recording_id: String,
+    pub device_id: String,
+    /// Datastore record id of the remote access session that was recorded.
+    pub remote_access_session_id: String,
+    pub session_type: String,
+    /// Account that opened the session, if known.
+    pub user_id: Option<String>,
+    /// RFC 3339 timestamp at which the recording started.
+    pub started_at: String,
+}
+
+impl RemoteAccessRecording {
+    pub fn pluck() -> Vec<String> {
+        vec![
+            "recording_id".into(),
+            "device_id".into(),
+            "remote_access_session_id".into(),
+            "session_type".into(),
+            "user_id".into(),
+            "started_at".into(),
+        ]
+    }
+
+    pub fn table() -> DBTable {
+        DBTable::RemoteAccessRecordings
+    }
+}
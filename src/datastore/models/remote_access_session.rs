@@ -1,5 +1,13 @@
 use crate::{datastore::db_tables::DBTable, utilities::random::generate_random_string};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Lifetime of a session when none is requested.
+pub const DEFAULT_SESSION_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Longest lifetime a session may be requested with.
+pub const MAX_SESSION_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Copy)]
 #[serde(rename_all = "lowercase")]
@@ -9,6 +17,16 @@ pub enum RemoteAccessType {
     Ui,
 }
 
+impl RemoteAccessType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RemoteAccessType::Ssh => "ssh",
+            RemoteAccessType::Tty => "tty",
+            RemoteAccessType::Ui => "ui",
+        }
+    }
+}
+
 impl TryFrom<&str> for RemoteAccessType {
     type Error = String;
 
@@ -26,31 +44,81 @@ impl TryFrom<&str> for RemoteAccessType {
     }
 }
 
+/// A remote access session, granting access to a device through the URL derived from its token.
+///
+/// Sessions expire at `expires_at`. SSH and TTY sessions can additionally be limited to
+/// `max_uses` connections; UI sessions are only bounded by their expiry, as every page
+/// load issues many requests.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RemoteAccessSession {
+    /// Datastore record id, assigned on creation.
+    #[serde(default, skip_serializing)]
+    pub id: String,
     pub device_id: String,
     #[serde(rename = "remote_access_session")]
     pub token: String,
     #[serde(rename = "remote_access_type")]
     pub r#type: RemoteAccessType,
+    /// Account that requested the session.
+    #[serde(rename = "owner_id", default)]
+    pub owner: Option<String>,
+    /// RFC 3339 timestamp after which the session can't be used.
+    /// Sessions created without one never were valid for long, and are considered expired.
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    #[serde(default)]
+    pub max_uses: Option<u32>,
+    #[serde(rename = "use_count", default)]
+    pub uses: u32,
 }
 
 impl RemoteAccessSession {
-    pub fn new(device_id: impl Into<String>, r#type: RemoteAccessType) -> Self {
+    pub fn new(
+        device_id: impl Into<String>,
+        r#type: RemoteAccessType,
+        owner: Option<String>,
+        lifetime: Duration,
+        max_uses: Option<u32>,
+    ) -> Self {
         let token = generate_random_string(32).to_ascii_lowercase();
 
+        let lifetime = chrono::Duration::from_std(lifetime.min(MAX_SESSION_LIFETIME))
+            .unwrap_or(chrono::Duration::zero());
+
         Self {
+            id: String::new(),
             device_id: device_id.into(),
             token,
             r#type,
+            owner,
+            expires_at: Some((Utc::now() + lifetime).to_rfc3339()),
+            max_uses,
+            uses: 0,
         }
     }
 
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .as_deref()
+            .and_then(|expires_at| DateTime::parse_from_rfc3339(expires_at).ok())
+            .is_none_or(|expires_at| expires_at <= Utc::now())
+    }
+
+    /// Returns `true` if the session reached its maximum number of uses.
+    pub fn is_used_up(&self) -> bool {
+        self.max_uses.is_some_and(|max_uses| self.uses >= max_uses)
+    }
+
     pub fn pluck() -> Vec<String> {
         vec![
+            "id".into(),
             "device_id".into(),
             "remote_access_session".into(),
             "remote_access_type".into(),
+            "owner_id".into(),
+            "expires_at".into(),
+            "max_uses".into(),
+            "use_count".into(),
         ]
     }
 
@@ -58,3 +126,66 @@ impl RemoteAccessSession {
         DBTable::RemoteAccessSessions
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_after_lifetime() {
+        let session = RemoteAccessSession::new(
+            "device",
+            RemoteAccessType::Ssh,
+            None,
+            DEFAULT_SESSION_LIFETIME,
+            None,
+        );
+        assert!(!session.is_expired());
+
+        let expired = RemoteAccessSession {
+            expires_at: Some((Utc::now() - chrono::Duration::seconds(1)).to_rfc3339()),
+            ..session.clone()
+        };
+        assert!(expired.is_expired());
+
+        let legacy = RemoteAccessSession {
+            expires_at: None,
+            ..session
+        };
+        assert!(legacy.is_expired());
+    }
+
+    #[test]
+    fn caps_lifetime() {
+        let session = RemoteAccessSession::new(
+            "device",
+            RemoteAccessType::Ui,
+            None,
+            MAX_SESSION_LIFETIME * 2,
+            None,
+        );
+
+        let expires_at = DateTime::parse_from_rfc3339(session.expires_at.as_deref().unwrap())
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert!(
+            expires_at <= Utc::now() + chrono::Duration::from_std(MAX_SESSION_LIFETIME).unwrap()
+        );
+    }
+
+    #[test]
+    fn used_up_after_max_uses() {
+        let mut session = RemoteAccessSession::new(
+            "device",
+            RemoteAccessType::Tty,
+            None,
+            DEFAULT_SESSION_LIFETIME,
+            Some(1),
+        );
+        assert!(!session.is_used_up());
+
+        session.uses = 1;
+        assert!(session.is_used_up());
+    }
+}
@@ -0,0 +1,43 @@
+use crate::datastore::db_tables::DBTable;
+use serde::{Deserialize, Serialize};
+
+/// A command line entered in a remote shell, extracted from the keystrokes of an SSH or TTY session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteShellCommand {
+    pub device_id: String,
+    /// Datastore record id of the remote access session the command was entered in.
+    pub remote_access_session_id: String,
+    pub session_type: String,
+    /// Account that entered the command, if known.
+    pub user_id: Option<String>,
+    /// Recording of the session, if it is recorded.
+    pub recording_id: Option<String>,
+    pub command: String,
+    /// Whether the line was edited in ways that can't be followed exactly,
+    /// e.g. with tab completion.
+    pub approximate: bool,
+    /// Whether the line was typed at a password prompt, in which case `command` is empty.
+    pub redacted: bool,
+    /// RFC 3339 timestamp at which the command was entered.
+    pub entered_at: String,
+}
+
+impl RemoteShellCommand {
+    pub fn pluck() -> Vec<String> {
+        vec![
+            "device_id".into(),
+            "remote_access_session_id".into(),
+            "session_type".into(),
+            "user_id".into(),
+            "recording_id".into(),
+            "command".into(),
+            "approximate".into(),
+            "redacted".into(),
+            "entered_at".into(),
+        ]
+    }
+
+    pub fn table() -> DBTable {
+        DBTable::RemoteShellCommands
+    }
+}
@@ -1,14 +1,32 @@
 use crate::datastore::db_tables::DBTable;
 use crate::utilities;
+use chrono::{DateTime, Utc};
 use nullnet_liberror::Error;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Lifetime of a keypair when none is configured.
+pub const DEFAULT_SSH_KEY_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SSHKeypair {
+    /// Datastore record id, assigned on creation.
+    #[serde(default, skip_serializing)]
+    pub id: String,
     pub device_id: String,
     pub public_key: String,
     pub private_key: String,
     pub passphrase: String,
+    /// Fingerprint of the device SSH host key, pinned on first connection.
+    #[serde(default)]
+    pub host_key_fingerprint: Option<String>,
+    /// RFC 3339 timestamp at which the keypair was generated.
+    /// Keypairs generated without one are considered expired.
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// Number of seconds after `created_at` at which the keypair is rotated.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
 }
 
 impl SSHKeypair {
@@ -17,30 +35,58 @@ impl SSHKeypair {
         public_key: impl Into<String>,
         private_key: impl Into<String>,
         passphrase: impl Into<String>,
+        ttl: Duration,
     ) -> Self {
         Self {
+            id: String::new(),
             device_id: device_id.into(),
             public_key: public_key.into(),
             private_key: private_key.into(),
             passphrase: passphrase.into(),
+            host_key_fingerprint: None,
+            created_at: Some(Utc::now().to_rfc3339()),
+            ttl_secs: Some(ttl.as_secs()),
         }
     }
 
-    pub async fn generate(device_id: &str) -> Result<Self, Error> {
+    pub async fn generate(device_id: &str, ttl: Duration) -> Result<Self, Error> {
         let passphrase = utilities::random::generate_random_string(16);
 
         let (public_key, private_key) =
             utilities::ssh::generate_keypair(Some(passphrase.clone()), None).await?;
 
-        Ok(Self::new(device_id, public_key, private_key, passphrase))
+        Ok(Self::new(
+            device_id,
+            public_key,
+            private_key,
+            passphrase,
+            ttl,
+        ))
+    }
+
+    /// Returns the time after which the keypair must be rotated, if known.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        let created_at = DateTime::parse_from_rfc3339(self.created_at.as_deref()?).ok()?;
+        let ttl = chrono::Duration::seconds(i64::try_from(self.ttl_secs?).ok()?);
+
+        created_at.with_timezone(&Utc).checked_add_signed(ttl)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at()
+            .is_none_or(|expires_at| expires_at <= Utc::now())
     }
 
     pub fn pluck() -> Vec<String> {
         vec![
+            "id".into(),
             "device_id".into(),
             "public_key".into(),
             "private_key".into(),
             "passphrase".into(),
+            "host_key_fingerprint".into(),
+            "created_at".into(),
+            "ttl_secs".into(),
         ]
     }
 
@@ -48,3 +94,27 @@ impl SSHKeypair {
         DBTable::SSHKeys
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_after_ttl() {
+        let keypair = SSHKeypair::new("device", "public", "private", "secret", DEFAULT_SSH_KEY_TTL);
+        assert!(!keypair.is_expired());
+
+        let expired = SSHKeypair {
+            created_at: Some((Utc::now() - chrono::Duration::days(31)).to_rfc3339()),
+            ..keypair.clone()
+        };
+        assert!(expired.is_expired());
+
+        let legacy = SSHKeypair {
+            created_at: None,
+            ttl_secs: None,
+            ..keypair
+        };
+        assert!(legacy.is_expired());
+    }
+}
@@ -0,0 +1,22 @@
+use nullnet_liberror::Error;
+use serde_json::json;
+
+use crate::datastore::builders::CreateRequestBuilder;
+use crate::datastore::{Datastore, RemoteAccessRecording};
+
+impl Datastore {
+    pub async fn create_recording(
+        &self,
+        token: &str,
+        recording: &RemoteAccessRecording,
+    ) -> Result<(), Error> {
+        let request = CreateRequestBuilder::new()
+            .pluck(RemoteAccessRecording::pluck())
+            .table(RemoteAccessRecording::table())
+            .record(json!(recording).to_string())
+            .build();
+
+        let _ = self.inner.clone().create(request, token).await?;
+        Ok(())
+    }
+}
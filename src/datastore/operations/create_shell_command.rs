@@ -0,0 +1,22 @@
+use nullnet_liberror::Error;
+use serde_json::json;
+
+use crate::datastore::builders::CreateRequestBuilder;
+use crate::datastore::{Datastore, RemoteShellCommand};
+
+impl Datastore {
+    pub async fn create_shell_command(
+        &self,
+        token: &str,
+        command: &RemoteShellCommand,
+    ) -> Result<(), Error> {
+        let request = CreateRequestBuilder::new()
+            .pluck(RemoteShellCommand::pluck())
+            .table(RemoteShellCommand::table())
+            .record(json!(command).to_string())
+            .build();
+
+        let _ = self.inner.clone().create(request, token).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use crate::datastore::Datastore;
+use crate::datastore::builders::{
+    AdvanceFilterBuilder, DeleteRequestBuilder, GetByFilterRequestBuilder,
+};
+use crate::datastore::db_tables::DBTable;
+use crate::utilities::json;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct CredentialsRecord {
+    id: String,
+}
+
+impl Datastore {
+    /// Permanently deletes all credentials registered for the given device,
+    /// so that it can no longer authenticate against the datastore.
+    pub async fn delete_device_credentials(
+        &self,
+        token: &str,
+        device_id: &str,
+    ) -> Result<(), Error> {
+        let filter = AdvanceFilterBuilder::new()
+            .field("device_id")
+            .values(format!("[\"{device_id}\"]"))
+            .r#type("criteria")
+            .operator("equal")
+            .entity(DBTable::DeviceCredentials)
+            .build();
+
+        let request = GetByFilterRequestBuilder::new()
+            .table(DBTable::DeviceCredentials)
+            .pluck("id")
+            .advance_filter(filter)
+            .build();
+
+        let response = self.inner.clone().get_by_filter(request, token).await?;
+
+        if response.count == 0 {
+            return Ok(());
+        }
+
+        let json_data = json::parse_string(&response.data)?;
+        let records =
+            serde_json::from_value::<Vec<CredentialsRecord>>(json_data).handle_err(location!())?;
+
+        for record in records {
+            let request = DeleteRequestBuilder::new()
+                .id(record.id)
+                .table(DBTable::DeviceCredentials)
+                .permanent(true)
+                .build();
+
+            self.inner.clone().delete(request, token).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,18 @@
+use crate::datastore::builders::DeleteRequestBuilder;
+use crate::datastore::{Datastore, RemoteAccessSession};
+use nullnet_liberror::Error;
+
+impl Datastore {
+    /// Permanently deletes a remote access session, revoking access through its URL.
+    pub async fn delete_session(&self, token: &str, session_id: &str) -> Result<(), Error> {
+        let request = DeleteRequestBuilder::new()
+            .id(session_id)
+            .table(RemoteAccessSession::table())
+            .permanent(true)
+            .build();
+
+        self.inner.clone().delete(request, token).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use crate::datastore::builders::{
+    AdvanceFilterBuilder, DeleteRequestBuilder, GetByFilterRequestBuilder,
+};
+use crate::datastore::{Datastore, SSHKeypair};
+use crate::utilities::json;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct KeypairRecord {
+    id: String,
+}
+
+impl Datastore {
+    /// Permanently deletes the keypairs of the given device other than `keep_id`,
+    /// so that rotated private keys are not kept around.
+    pub async fn delete_ssh_keypairs_except(
+        &self,
+        token: &str,
+        device_id: &str,
+        keep_id: &str,
+    ) -> Result<(), Error> {
+        let filter = AdvanceFilterBuilder::new()
+            .field("device_id")
+            .values(format!("[\"{device_id}\"]"))
+            .r#type("criteria")
+            .operator("equal")
+            .entity(SSHKeypair::table())
+            .build();
+
+        let request = GetByFilterRequestBuilder::new()
+            .table(SSHKeypair::table())
+            .pluck("id")
+            .advance_filter(filter)
+            .build();
+
+        let response = self.inner.clone().get_by_filter(request, token).await?;
+
+        if response.count == 0 {
+            return Ok(());
+        }
+
+        let json_data = json::parse_string(&response.data)?;
+        let records =
+            serde_json::from_value::<Vec<KeypairRecord>>(json_data).handle_err(location!())?;
+
+        for record in records.into_iter().filter(|record| record.id != keep_id) {
+            let request = DeleteRequestBuilder::new()
+                .id(record.id)
+                .table(SSHKeypair::table())
+                .permanent(true)
+                .build();
+
+            self.inner.clone().delete(request, token).await?;
+        }
+
+        Ok(())
+    }
+}
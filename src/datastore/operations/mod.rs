@@ -4,18 +4,26 @@ mod create_connections;
 mod create_device;
 mod create_interfaces;
 mod create_ip_info;
+mod create_recording;
 mod create_rules;
 mod create_session;
+mod create_shell_command;
 mod create_ssh_keypair;
 mod create_system_resources;
+mod delete_device_credentials;
+mod delete_session;
+mod delete_ssh_keypairs;
 mod is_ip_info_stored;
 mod login;
 mod obtain_config;
 mod obtain_device;
 mod obtain_installation_code;
+mod obtain_recordings;
 mod obtain_session;
 mod obtain_ssh_keypair;
 mod redeem_installation_code;
 mod register_device;
 mod update_config;
 mod update_device;
+mod update_session;
+mod update_ssh_keypair;
@@ -1,10 +1,14 @@
 use crate::datastore::builders::{AdvanceFilterBuilder, GetByFilterRequestBuilder};
 use crate::datastore::db_tables::DBTable;
-use crate::datastore::{Datastore, DeviceConfiguration};
+use crate::datastore::{ConfigurationStatus, Datastore, DeviceConfiguration};
 use crate::utilities;
 use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use serde_json::json;
 
 impl Datastore {
+    /// Fetches the latest configuration known to be running on the device.
+    ///
+    /// Drafts not yet applied, and configurations the device failed to apply, are ignored.
     pub async fn obtain_config(
         &self,
         token: &str,
@@ -18,12 +22,25 @@ impl Datastore {
             .entity(DBTable::DeviceConfigurations)
             .build();
 
+        let and = AdvanceFilterBuilder::new()
+            .r#type("operator")
+            .operator("and")
+            .build();
+
+        let status_filter = AdvanceFilterBuilder::new()
+            .field("config_status")
+            .values(format!("[{}]", json!(ConfigurationStatus::Applied)))
+            .r#type("criteria")
+            .operator("equal")
+            .entity(DBTable::DeviceConfigurations)
+            .build();
+
         let request = GetByFilterRequestBuilder::new()
             .plucks(DeviceConfiguration::pluck())
             .order_by("timestamp")
             .order_direction("desc")
             .limit(1)
-            .advance_filter(filter)
+            .advance_filters([filter, and, status_filter])
             .table(DBTable::DeviceConfigurations)
             .case_sensitive_sorting(true)
             .build();
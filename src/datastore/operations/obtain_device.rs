@@ -71,4 +71,72 @@ impl Datastore {
         let device = serde_json::from_value::<Device>(data).handle_err(location!())?;
         Ok(Some(device))
     }
+
+    /// Fetches a page of the devices of the given organization, most recent first.
+    pub async fn obtain_devices(
+        &self,
+        token: &str,
+        organization_id: &str,
+        limit: i32,
+        offset: i32,
+    ) -> Result<Vec<Device>, Error> {
+        let filter = AdvanceFilterBuilder::new()
+            .field("organization_id")
+            .values(format!("[\"{organization_id}\"]"))
+            .r#type("criteria")
+            .operator("equal")
+            .entity(Device::table())
+            .build();
+
+        let request = GetByFilterRequestBuilder::new()
+            .table(Device::table())
+            .plucks(Device::pluck())
+            .advance_filter(filter)
+            .limit(limit)
+            .offset(offset)
+            .order_by("timestamp")
+            .order_direction("desc")
+            .case_sensitive_sorting(true)
+            .build();
+
+        let response = self.inner.clone().get_by_filter(request, token).await?;
+
+        if response.count == 0 {
+            return Ok(vec![]);
+        }
+
+        let json_data = json::parse_string(&response.data)?;
+
+        let devices = serde_json::from_value::<Vec<Device>>(json_data).handle_err(location!())?;
+        Ok(devices)
+    }
+
+    /// Fetches all devices currently flagged as online, across all organizations.
+    pub async fn obtain_online_devices(&self, token: &str) -> Result<Vec<Device>, Error> {
+        let filter = AdvanceFilterBuilder::new()
+            .field("is_device_online")
+            .values("[true]")
+            .r#type("criteria")
+            .operator("equal")
+            .entity(Device::table())
+            .build();
+
+        let request = GetByFilterRequestBuilder::new()
+            .table(Device::table())
+            .plucks(Device::pluck())
+            .advance_filter(filter)
+            .performed_by_root(true)
+            .build();
+
+        let response = self.inner.clone().get_by_filter(request, token).await?;
+
+        if response.count == 0 {
+            return Ok(vec![]);
+        }
+
+        let json_data = json::parse_string(&response.data)?;
+
+        let devices = serde_json::from_value::<Vec<Device>>(json_data).handle_err(location!())?;
+        Ok(devices)
+    }
 }
@@ -0,0 +1,41 @@
+use crate::datastore::builders::{AdvanceFilterBuilder, GetByFilterRequestBuilder};
+use crate::datastore::{Datastore, RemoteAccessRecording};
+use crate::utilities::json;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+
+impl Datastore {
+    /// Fetches the recordings of a remote access session, most recent first.
+    pub async fn obtain_recordings(
+        &self,
+        token: &str,
+        session_id: &str,
+    ) -> Result<Vec<RemoteAccessRecording>, Error> {
+        let filter = AdvanceFilterBuilder::new()
+            .field("remote_access_session_id")
+            .values(format!("[\"{session_id}\"]"))
+            .r#type("criteria")
+            .operator("equal")
+            .entity(RemoteAccessRecording::table())
+            .build();
+
+        let request = GetByFilterRequestBuilder::new()
+            .table(RemoteAccessRecording::table())
+            .plucks(RemoteAccessRecording::pluck())
+            .advance_filter(filter)
+            .order_by("timestamp")
+            .order_direction("desc")
+            .build();
+
+        let response = self.inner.clone().get_by_filter(request, token).await?;
+
+        if response.count == 0 {
+            return Ok(vec![]);
+        }
+
+        let json_data = json::parse_string(&response.data)?;
+
+        let recordings = serde_json::from_value::<Vec<RemoteAccessRecording>>(json_data)
+            .handle_err(location!())?;
+        Ok(recordings)
+    }
+}
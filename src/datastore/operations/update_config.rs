@@ -1,5 +1,6 @@
 use crate::datastore::{
-    Datastore, DeviceConfiguration, builders::UpdateRequestBuilder, db_tables::DBTable,
+    ConfigurationStatus, Datastore, DeviceConfiguration, builders::UpdateRequestBuilder,
+    db_tables::DBTable,
 };
 use nullnet_liberror::Error;
 use serde_json::json;
@@ -21,4 +22,25 @@ impl Datastore {
 
         Ok(())
     }
+
+    /// Updates the status of a configuration, along with the error the device reported, if any.
+    pub async fn update_config_status(
+        &self,
+        token: &str,
+        config_id: &str,
+        status: ConfigurationStatus,
+        error: Option<&str>,
+        performed_by_root: bool,
+    ) -> Result<bool, Error> {
+        let request = UpdateRequestBuilder::new()
+            .id(config_id)
+            .table(DBTable::DeviceConfigurations)
+            .body(json!({ "config_status": status, "config_error": error }).to_string())
+            .performed_by_root(performed_by_root)
+            .build();
+
+        let data = self.inner.clone().update(request, token).await?;
+
+        Ok(data.count == 1)
+    }
 }
@@ -24,11 +24,30 @@ impl Datastore {
         Ok(data.count == 1)
     }
 
+    /// Pins the certificate of the device web UI, or clears the pin if `fingerprint` is `None`.
+    pub async fn update_device_ui_certificate_fingerprint(
+        &self,
+        token: &str,
+        device_id: &str,
+        fingerprint: Option<&str>,
+    ) -> Result<bool, Error> {
+        let request = UpdateRequestBuilder::new()
+            .id(device_id)
+            .table(DBTable::Devices)
+            .body(json!({ "ui_tls_certificate_fingerprint": fingerprint }).to_string())
+            .build();
+
+        let data = self.inner.clone().update(request, token).await?;
+
+        Ok(data.count == 1)
+    }
+
     pub async fn update_device_online_status(
         &self,
         token: &str,
         device_uuid: &str,
         is_online: bool,
+        performed_by_root: bool,
     ) -> Result<(), Error> {
         let updates = json!({
             "is_device_online": is_online
@@ -47,10 +66,38 @@ impl Datastore {
             .table(Device::table())
             .updates(updates)
             .advance_filter(filter)
+            .performed_by_root(performed_by_root)
             .build();
 
         let _ = self.inner.clone().batch_update(request, token).await;
 
         Ok(())
     }
+
+    /// Marks every device that is currently flagged as online as offline, across all organizations.
+    pub async fn mark_all_devices_offline(&self, token: &str) -> Result<(), Error> {
+        let updates = json!({
+            "is_device_online": false
+        })
+        .to_string();
+
+        let filter = AdvanceFilterBuilder::new()
+            .field("is_device_online")
+            .values("[true]")
+            .r#type("criteria")
+            .operator("equal")
+            .entity(Device::table())
+            .build();
+
+        let request = BatchUpdateRequestBuilder::new()
+            .table(Device::table())
+            .updates(updates)
+            .advance_filter(filter)
+            .performed_by_root(true)
+            .build();
+
+        let _ = self.inner.clone().batch_update(request, token).await?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,48 @@
+use crate::datastore::builders::{AdvanceFilterBuilder, BatchUpdateRequestBuilder};
+use crate::datastore::{Datastore, RemoteAccessSession};
+use nullnet_liberror::Error;
+use serde_json::json;
+
+impl Datastore {
+    /// Increments the use count of a session, provided it is still `current_uses`.
+    ///
+    /// Returns `false` if the session was used or deleted concurrently,
+    /// in which case nothing is updated.
+    pub async fn increment_session_uses(
+        &self,
+        token: &str,
+        session_id: &str,
+        current_uses: u32,
+    ) -> Result<bool, Error> {
+        let id_filter = AdvanceFilterBuilder::new()
+            .field("id")
+            .values(format!("[\"{session_id}\"]"))
+            .r#type("criteria")
+            .operator("equal")
+            .entity(RemoteAccessSession::table())
+            .build();
+
+        let and = AdvanceFilterBuilder::new()
+            .r#type("operator")
+            .operator("and")
+            .build();
+
+        let uses_filter = AdvanceFilterBuilder::new()
+            .field("use_count")
+            .values(format!("[{current_uses}]"))
+            .r#type("criteria")
+            .operator("equal")
+            .entity(RemoteAccessSession::table())
+            .build();
+
+        let request = BatchUpdateRequestBuilder::new()
+            .table(RemoteAccessSession::table())
+            .updates(json!({ "use_count": current_uses + 1 }).to_string())
+            .advance_filters([id_filter, and, uses_filter])
+            .build();
+
+        let data = self.inner.clone().batch_update(request, token).await?;
+
+        Ok(data.count == 1)
+    }
+}
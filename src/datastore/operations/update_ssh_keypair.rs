@@ -0,0 +1,24 @@
+use crate::datastore::builders::UpdateRequestBuilder;
+use crate::datastore::{Datastore, SSHKeypair};
+use nullnet_liberror::Error;
+use serde_json::json;
+
+impl Datastore {
+    /// Pins the SSH host key of the device, or clears the pin if `fingerprint` is `None`.
+    pub async fn update_ssh_host_key_fingerprint(
+        &self,
+        token: &str,
+        keypair_id: &str,
+        fingerprint: Option<&str>,
+    ) -> Result<bool, Error> {
+        let request = UpdateRequestBuilder::new()
+            .id(keypair_id)
+            .table(SSHKeypair::table())
+            .body(json!({ "host_key_fingerprint": fingerprint }).to_string())
+            .build();
+
+        let data = self.inner.clone().update(request, token).await?;
+
+        Ok(data.count == 1)
+    }
+}
@@ -0,0 +1,181 @@
+use crate::app_context::AppContext;
+use crate::datastore::ConfigurationStatus;
+use crate::datastore::DeviceConfiguration;
+use crate::http_proxy::utilities::caller::{Caller, Role};
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use crate::orchestrator::{CommandStatus, PendingCommand};
+use crate::utilities;
+use actix_web::HttpResponse;
+use actix_web::Responder;
+use actix_web::web::Data;
+use actix_web::web::Json;
+use libfireparse::{Alias, Rule};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+/// Time the device is given to apply a configuration before its draft is marked as failed.
+const APPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+pub struct RequestPayload {
+    device_id: String,
+    #[serde(default)]
+    rules: Vec<Rule>,
+    #[serde(default)]
+    aliases: Vec<Alias>,
+}
+
+pub async fn apply_configuration(
+    caller: Caller,
+    context: Data<AppContext>,
+    body: Json<RequestPayload>,
+) -> impl Responder {
+    if let Err(resp) = caller.require(Role::Operator) {
+        return resp;
+    }
+
+    let Ok(device) = context
+        .datastore
+        .obtain_device_by_id(&caller.jwt, &body.device_id, false)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device record"));
+    };
+
+    let Some(device) = device else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Device not found"));
+    };
+
+    if let Err(resp) = caller.ensure_device_access(&device) {
+        return resp;
+    }
+
+    if !device.authorized {
+        return HttpResponse::BadRequest().json(ErrorJson::from("Device is not authorized yet"));
+    }
+
+    let Some(client) = context.orchestractor.get_client(&device.uuid).await else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Device is not online"));
+    };
+
+    let rules = json!(body.rules).to_string();
+    let aliases = json!(body.aliases).to_string();
+
+    let config = DeviceConfiguration {
+        device_id: device.id.clone(),
+        digest: utilities::hash::md5_digest(&format!("{rules}{aliases}")),
+        status: ConfigurationStatus::Draft,
+        ..Default::default()
+    };
+
+    let config_id = match context.datastore.create_config(&caller.jwt, &config).await {
+        Ok(id) => id,
+        Err(err) => return HttpResponse::InternalServerError().json(ErrorJson::from(err)),
+    };
+
+    let result = tokio::join!(
+        context
+            .datastore
+            .create_rules(&caller.jwt, &body.rules, &config_id),
+        context
+            .datastore
+            .create_aliases(&caller.jwt, &body.aliases, &config_id),
+    );
+
+    if result.0.is_err() || result.1.is_err() {
+        let message = "Failed to store configuration";
+        fail_draft(&context, &caller.jwt, &config_id, message).await;
+        return HttpResponse::InternalServerError().json(ErrorJson::from(message));
+    }
+
+    let command = client
+        .lock()
+        .await
+        .apply_configuration(&config_id, rules, aliases)
+        .await;
+
+    let command = match command {
+        Ok(command) => command,
+        Err(err) => {
+            fail_draft(&context, &caller.jwt, &config_id, err.to_str()).await;
+            return HttpResponse::InternalServerError().json(ErrorJson::from(err));
+        }
+    };
+
+    tokio::spawn(track_configuration(
+        context.clone(),
+        config_id.clone(),
+        command,
+    ));
+
+    HttpResponse::Accepted().json(json!({"configuration_id": config_id}))
+}
+
+/// Marks a draft that never reached the device as failed.
+async fn fail_draft(context: &AppContext, token: &str, config_id: &str, error: &str) {
+    if let Err(err) = context
+        .datastore
+        .update_config_status(
+            token,
+            config_id,
+            ConfigurationStatus::Failed,
+            Some(error),
+            false,
+        )
+        .await
+    {
+        log::error!(
+            "Failed to mark configuration {config_id} as failed: {}",
+            err.to_str()
+        );
+    }
+}
+
+/// Records the result of a configuration sent to the device.
+///
+/// Drafts the device doesn't report on within `APPLY_TIMEOUT` are marked as failed.
+/// The caller's token may expire in the meantime, so the update is performed as root.
+async fn track_configuration(
+    context: Data<AppContext>,
+    config_id: String,
+    command: PendingCommand,
+) {
+    let (status, error) = match command.wait_for(APPLY_TIMEOUT).await {
+        Ok(CommandStatus::Applied) => {
+            log::info!("Device applied configuration {config_id}");
+            (ConfigurationStatus::Applied, None)
+        }
+        Ok(CommandStatus::Unacknowledged) => {
+            log::warn!("Device did not report on configuration {config_id}");
+            (
+                ConfigurationStatus::Failed,
+                Some(String::from("Timed out waiting for the device to respond")),
+            )
+        }
+        Err(err) => {
+            log::warn!(
+                "Configuration {config_id} was not applied: {}",
+                err.to_str()
+            );
+            (ConfigurationStatus::Failed, Some(err.to_str().to_string()))
+        }
+    };
+
+    let Ok(token) = context.root_token_provider.get().await else {
+        log::error!("Failed to obtain root token to record configuration {config_id}");
+        return;
+    };
+
+    if let Err(err) = context
+        .datastore
+        .update_config_status(&token.jwt, &config_id, status, error.as_deref(), true)
+        .await
+    {
+        log::error!(
+            "Failed to record the result of configuration {config_id}: {}",
+            err.to_str()
+        );
+    }
+}
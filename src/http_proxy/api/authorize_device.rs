@@ -1,9 +1,8 @@
 use crate::app_context::AppContext;
-use crate::http_proxy::utilities::authorization;
+use crate::http_proxy::utilities::caller::{Caller, Role};
 use crate::http_proxy::utilities::error_json::ErrorJson;
 use crate::protocol::wallguard_commands::AuthenticationData;
 use crate::utilities;
-use actix_web::HttpRequest;
 use actix_web::HttpResponse;
 use actix_web::Responder;
 use actix_web::web::Data;
@@ -17,17 +16,17 @@ pub struct RequestPayload {
 }
 
 pub async fn authorize_device(
-    request: HttpRequest,
+    caller: Caller,
     context: Data<AppContext>,
     body: Json<RequestPayload>,
 ) -> impl Responder {
-    let Some(jwt) = authorization::extract_authorization_token(&request) else {
-        return HttpResponse::Unauthorized().json(ErrorJson::from("Missing Authorization header"));
-    };
+    if let Err(resp) = caller.require(Role::Admin) {
+        return resp;
+    }
 
     let Ok(value) = context
         .datastore
-        .obtain_device_by_id(&jwt, &body.device_id, false)
+        .obtain_device_by_id(&caller.jwt, &body.device_id, false)
         .await
     else {
         return HttpResponse::InternalServerError()
@@ -38,6 +37,10 @@ pub async fn authorize_device(
         return HttpResponse::BadRequest().json(ErrorJson::from("Device not found"));
     };
 
+    if let Err(resp) = caller.ensure_device_access(&device) {
+        return resp;
+    }
+
     let Some(client) = context.orchestractor.get_client(&device.uuid).await else {
         return HttpResponse::InternalServerError()
             .json(ErrorJson::from("Device is not connected"));
@@ -53,12 +56,27 @@ pub async fn authorize_device(
 
     device.authorized = true;
 
+    let certificate = match &context.device_ca {
+        Some(device_ca) => match device_ca.issue(&device.id).await {
+            Ok(certificate) => Some(certificate),
+            Err(_) => {
+                return HttpResponse::InternalServerError()
+                    .json(ErrorJson::from("Failed to issue device certificate"));
+            }
+        },
+        None => None,
+    };
+
+    device.certificate_fingerprint = certificate
+        .as_ref()
+        .map(|certificate| certificate.fingerprint.clone());
+
     let account_id = utilities::random::generate_random_string(12);
     let account_secret = utilities::random::generate_random_string(36);
 
     if context
         .datastore
-        .register_device(&jwt, &account_id, &account_secret, &device)
+        .register_device(&caller.jwt, &account_id, &account_secret, &device)
         .await
         .is_err()
     {
@@ -68,7 +86,7 @@ pub async fn authorize_device(
 
     if context
         .datastore
-        .update_device(&jwt, &body.device_id, &device)
+        .update_device(&caller.jwt, &body.device_id, &device)
         .await
         .is_err()
     {
@@ -82,6 +100,10 @@ pub async fn authorize_device(
         .authorize(AuthenticationData {
             app_id: Some(account_id),
             app_secret: Some(account_secret),
+            client_certificate: certificate
+                .as_ref()
+                .map(|certificate| certificate.certificate.clone()),
+            client_key: certificate.map(|certificate| certificate.private_key),
         })
         .await
         .is_err()
@@ -0,0 +1,86 @@
+use crate::app_context::AppContext;
+use crate::http_proxy::utilities::caller::{Caller, Role};
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use actix_web::HttpResponse;
+use actix_web::Responder;
+use actix_web::web::Data;
+use actix_web::web::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+pub struct DeauthorizeRequestPayload {
+    device_id: String,
+}
+
+pub async fn deauthorize_device(
+    caller: Caller,
+    context: Data<AppContext>,
+    body: Json<DeauthorizeRequestPayload>,
+) -> impl Responder {
+    if let Err(resp) = caller.require(Role::Admin) {
+        return resp;
+    }
+
+    let Ok(value) = context
+        .datastore
+        .obtain_device_by_id(&caller.jwt, &body.device_id, false)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device record"));
+    };
+
+    let Some(mut device) = value else {
+        return HttpResponse::BadRequest().json(ErrorJson::from("Device not found"));
+    };
+
+    if let Err(resp) = caller.ensure_device_access(&device) {
+        return resp;
+    }
+
+    // Revocation runs first and the flag is flipped last, so that a request failing
+    // halfway can be retried: credentials and tunnels are revoked again even if the
+    // device is already flagged as unauthorized.
+    if context
+        .datastore
+        .delete_device_credentials(&caller.jwt, &device.id)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to delete device credentials"));
+    }
+
+    // The device might be offline, in which case it will be treated as
+    // unauthorized as soon as it reconnects.
+    if let Some(client) = context.orchestractor.get_client(&device.uuid).await {
+        let mut lock = client.lock().await;
+
+        if lock.deauthorize().await.is_err() {
+            log::warn!(
+                "Failed to notify device {} about its deauthorization",
+                device.uuid
+            );
+        }
+    }
+
+    if !device.authorized && device.certificate_fingerprint.is_none() {
+        return HttpResponse::Ok().json(json!({}));
+    }
+
+    device.authorized = false;
+    device.certificate_fingerprint = None;
+
+    if context
+        .datastore
+        .update_device(&caller.jwt, &body.device_id, &device)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to update device record"));
+    };
+
+    HttpResponse::Ok().json(json!({}))
+}
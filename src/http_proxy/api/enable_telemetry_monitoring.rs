@@ -1,7 +1,7 @@
 use crate::app_context::AppContext;
-use crate::http_proxy::utilities::authorization;
+use crate::http_proxy::utilities::caller::{Caller, Role};
 use crate::http_proxy::utilities::error_json::ErrorJson;
-use actix_web::HttpRequest;
+use crate::orchestrator::CommandStatus;
 use actix_web::HttpResponse;
 use actix_web::Responder;
 
@@ -17,17 +17,17 @@ pub struct RequestPayload {
 }
 
 pub async fn enable_telemetry_monitoring(
-    request: HttpRequest,
+    caller: Caller,
     context: Data<AppContext>,
     body: Json<RequestPayload>,
 ) -> impl Responder {
-    let Some(jwt) = authorization::extract_authorization_token(&request) else {
-        return HttpResponse::Unauthorized().json(ErrorJson::from("Missing Authorization header"));
-    };
+    if let Err(resp) = caller.require(Role::Operator) {
+        return resp;
+    }
 
     let Ok(device) = context
         .datastore
-        .obtain_device_by_id(&jwt, &body.device_id, false)
+        .obtain_device_by_id(&caller.jwt, &body.device_id, false)
         .await
     else {
         return HttpResponse::InternalServerError()
@@ -40,6 +40,10 @@ pub async fn enable_telemetry_monitoring(
 
     let mut device = device.unwrap();
 
+    if let Err(resp) = caller.ensure_device_access(&device) {
+        return resp;
+    }
+
     if !device.authorized {
         return HttpResponse::BadRequest().json(ErrorJson::from("Device is not authorized yet"));
     }
@@ -48,7 +52,7 @@ pub async fn enable_telemetry_monitoring(
 
     if context
         .datastore
-        .update_device(&jwt, &body.device_id, &device)
+        .update_device(&caller.jwt, &body.device_id, &device)
         .await
         .is_err()
     {
@@ -60,14 +64,20 @@ pub async fn enable_telemetry_monitoring(
         return HttpResponse::NotFound().json(ErrorJson::from("Device is not online"));
     };
 
-    if let Err(err) = client
+    let command = match client
         .lock()
         .await
         .enable_telemetry_monitoring(body.enable)
         .await
     {
-        return HttpResponse::InternalServerError().json(ErrorJson::from(err));
-    }
+        Ok(command) => command,
+        Err(err) => return HttpResponse::InternalServerError().json(ErrorJson::from(err)),
+    };
 
-    HttpResponse::Ok().json(json!({}))
+    // Commands the device doesn't acknowledge were still delivered, and may well be applied.
+    match command.wait().await {
+        Ok(CommandStatus::Applied) => HttpResponse::Ok().json(json!({"status": "applied"})),
+        Ok(status) => HttpResponse::Accepted().json(json!({"status": status.as_str()})),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorJson::from(err)),
+    }
 }
@@ -0,0 +1,37 @@
+use crate::app_context::AppContext;
+use crate::http_proxy::api::DeviceStatus;
+use crate::http_proxy::utilities::caller::{Caller, Role};
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use actix_web::HttpResponse;
+use actix_web::Responder;
+use actix_web::web::Data;
+use actix_web::web::Path;
+
+pub async fn get_device(
+    caller: Caller,
+    context: Data<AppContext>,
+    device_id: Path<String>,
+) -> impl Responder {
+    if let Err(resp) = caller.require(Role::Viewer) {
+        return resp;
+    }
+
+    let Ok(device) = context
+        .datastore
+        .obtain_device_by_id(&caller.jwt, &device_id, false)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device record"));
+    };
+
+    let Some(device) = device else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Device not found"));
+    };
+
+    if let Err(resp) = caller.ensure_device_access(&device) {
+        return resp;
+    }
+
+    HttpResponse::Ok().json(DeviceStatus::new(&context, device).await)
+}
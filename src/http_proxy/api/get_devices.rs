@@ -0,0 +1,82 @@
+use crate::app_context::AppContext;
+use crate::datastore::Device;
+use crate::http_proxy::utilities::caller::{Caller, Role};
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use crate::orchestrator::ClientInfo;
+use actix_web::HttpResponse;
+use actix_web::Responder;
+use actix_web::web::Data;
+use actix_web::web::Query;
+use serde::Deserialize;
+use serde::Serialize;
+
+const DEFAULT_LIMIT: i32 = 100;
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    limit: Option<i32>,
+    offset: Option<i32>,
+}
+
+/// Datastore record of a device merged with the live state of its control connection.
+#[derive(Serialize)]
+pub struct DeviceStatus {
+    #[serde(flatten)]
+    device: Device,
+    connected: bool,
+    connection: Option<ClientInfo>,
+}
+
+impl DeviceStatus {
+    pub async fn new(context: &AppContext, device: Device) -> Self {
+        let connection = match context.orchestractor.get_client(&device.uuid).await {
+            Some(client) => Some(client.lock().await.info()),
+            None => None,
+        };
+
+        Self {
+            device,
+            connected: connection.is_some(),
+            connection,
+        }
+    }
+}
+
+pub async fn get_devices(
+    caller: Caller,
+    context: Data<AppContext>,
+    query: Query<QueryParams>,
+) -> impl Responder {
+    if let Err(resp) = caller.require(Role::Viewer) {
+        return resp;
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    let offset = query.offset.unwrap_or_default();
+
+    if limit < 0 || offset < 0 {
+        return HttpResponse::BadRequest()
+            .json(ErrorJson::from("Limit and offset must not be negative"));
+    }
+
+    let Ok(devices) = context
+        .datastore
+        .obtain_devices(&caller.jwt, &caller.organization_id, limit, offset)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device records"));
+    };
+
+    let mut statuses = Vec::with_capacity(devices.len());
+
+    // The datastore filters by organization already, this only guards against it not doing so.
+    for device in devices
+        .into_iter()
+        .filter(|device| caller.ensure_device_access(device).is_ok())
+    {
+        statuses.push(DeviceStatus::new(&context, device).await);
+    }
+
+    HttpResponse::Ok().json(statuses)
+}
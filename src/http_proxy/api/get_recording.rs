@@ -0,0 +1,116 @@
+use crate::app_context::AppContext;
+use crate::http_proxy::utilities::caller::{Caller, Role};
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use crate::session_recording::RecordingReader;
+use actix_web::HttpResponse;
+use actix_web::Responder;
+use actix_web::web::Bytes;
+use actix_web::web::Data;
+use actix_web::web::Path;
+use serde::Deserialize;
+use std::io;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+/// Size of the chunks a recording is streamed in.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Fields of the recording header used to authorize playback.
+#[derive(Deserialize)]
+struct RecordingHeader {
+    device_id: String,
+    user_id: Option<String>,
+}
+
+/// Streams a session recording in the asciicast v2 format.
+///
+/// Operators can play back their own sessions, admins any session on the devices
+/// of their organization.
+pub async fn get_recording(
+    caller: Caller,
+    context: Data<AppContext>,
+    recording_id: Path<String>,
+) -> impl Responder {
+    if let Err(resp) = caller.require(Role::Operator) {
+        return resp;
+    }
+
+    let Some(recorder) = &context.recorder else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Session recording is disabled"));
+    };
+
+    let reader = match recorder.open(&recording_id).await {
+        Ok(Some(reader)) => reader,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorJson::from("Recording not found"));
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError()
+                .json(ErrorJson::from("Failed to open recording"));
+        }
+    };
+
+    let mut reader = BufReader::new(reader);
+    let mut header_line = String::new();
+
+    let header = match reader.read_line(&mut header_line).await {
+        Ok(_) => serde_json::from_str::<RecordingHeader>(&header_line).ok(),
+        Err(_) => None,
+    };
+
+    let Some(header) = header else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Malformed recording header"));
+    };
+
+    let Ok(device) = context
+        .datastore
+        .obtain_device_by_id(&caller.jwt, &header.device_id, false)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device record"));
+    };
+
+    let Some(device) = device else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Recording not found"));
+    };
+
+    if caller.ensure_device_access(&device).is_err() {
+        return HttpResponse::NotFound().json(ErrorJson::from("Recording not found"));
+    }
+
+    let is_own_session = header.user_id.as_deref() == Some(caller.account_id.as_str());
+
+    let access = if is_own_session {
+        Ok(())
+    } else {
+        caller.require(Role::Admin)
+    };
+
+    if let Err(resp) = access {
+        return resp;
+    }
+
+    let header = futures_util::stream::once(async move { Ok(Bytes::from(header_line)) });
+
+    HttpResponse::Ok()
+        .content_type("application/x-asciicast")
+        .streaming(futures_util::StreamExt::chain(header, read_chunks(reader)))
+}
+
+fn read_chunks(
+    reader: BufReader<RecordingReader>,
+) -> impl futures_util::Stream<Item = io::Result<Bytes>> {
+    futures_util::stream::try_unfold(reader, |mut reader| async move {
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let read = reader.read(&mut buffer).await?;
+
+        if read == 0 {
+            return Ok(None);
+        }
+
+        buffer.truncate(read);
+
+        Ok(Some((Bytes::from(buffer), reader)))
+    })
+}
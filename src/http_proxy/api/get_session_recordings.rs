@@ -0,0 +1,69 @@
+use crate::app_context::AppContext;
+use crate::http_proxy::utilities::caller::{Caller, Role};
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use actix_web::HttpResponse;
+use actix_web::Responder;
+use actix_web::web::Data;
+use actix_web::web::Path;
+
+/// Lists the recordings of a remote access session, most recent first.
+///
+/// Each connection through the session is recorded separately. The ids returned
+/// here are the ones recordings are played back with.
+pub async fn get_session_recordings(
+    caller: Caller,
+    context: Data<AppContext>,
+    session_token: Path<String>,
+) -> impl Responder {
+    if let Err(resp) = caller.require(Role::Operator) {
+        return resp;
+    }
+
+    let Ok(session) = context
+        .datastore
+        .obtain_session(&caller.jwt, &session_token)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch session record"));
+    };
+
+    let Some(session) = session else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Session not found"));
+    };
+
+    let Ok(device) = context
+        .datastore
+        .obtain_device_by_id(&caller.jwt, &session.device_id, false)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device record"));
+    };
+
+    let Some(device) = device else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Session not found"));
+    };
+
+    if caller.ensure_device_access(&device).is_err() {
+        return HttpResponse::NotFound().json(ErrorJson::from("Session not found"));
+    }
+
+    // Sessions without a recorded owner are left to admins.
+    let is_owner = session.owner.as_deref() == Some(caller.account_id.as_str());
+
+    if !is_owner && caller.require(Role::Admin).is_err() {
+        return HttpResponse::Forbidden()
+            .json(ErrorJson::from("Session belongs to another account"));
+    }
+
+    match context
+        .datastore
+        .obtain_recordings(&caller.jwt, &session.id)
+        .await
+    {
+        Ok(recordings) => HttpResponse::Ok().json(recordings),
+        Err(_) => HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch session recordings")),
+    }
+}
@@ -1,11 +1,31 @@
+mod apply_configuration;
 mod authorize_device;
+mod deauthorize_device;
 mod enable_config_monitoring;
 mod enable_telemetry_monitoring;
 mod enable_traffic_monitoring;
+mod get_device;
+mod get_devices;
+mod get_recording;
+mod get_session_recordings;
 mod request_session;
+mod revoke_session;
+mod rotate_ssh_keypair;
+mod update_device_ssh_settings;
+mod update_device_ui_settings;
 
+pub use apply_configuration::*;
 pub use authorize_device::*;
+pub use deauthorize_device::*;
 pub use enable_config_monitoring::*;
 pub use enable_telemetry_monitoring::*;
 pub use enable_traffic_monitoring::*;
+pub use get_device::*;
+pub use get_devices::*;
+pub use get_recording::*;
+pub use get_session_recordings::*;
 pub use request_session::*;
+pub use revoke_session::*;
+pub use rotate_ssh_keypair::*;
+pub use update_device_ssh_settings::*;
+pub use update_device_ui_settings::*;
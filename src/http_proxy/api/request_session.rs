@@ -1,4 +1,3 @@
-use actix_web::HttpRequest;
 use actix_web::HttpResponse;
 use actix_web::Responder;
 use actix_web::web::Data;
@@ -6,28 +5,37 @@ use actix_web::web::Json;
 use nullnet_liberror::Error;
 use serde::Deserialize;
 use serde_json::json;
+use std::time::Duration;
 
 use crate::app_context::AppContext;
+use crate::datastore::DEFAULT_SESSION_LIFETIME;
+use crate::datastore::MAX_SESSION_LIFETIME;
 use crate::datastore::RemoteAccessSession;
 use crate::datastore::RemoteAccessType;
-use crate::datastore::SSHKeypair;
-use crate::http_proxy::utilities::authorization;
+use crate::http_proxy::config::HttpProxyConfig;
+use crate::http_proxy::utilities::caller::{Caller, Role};
 use crate::http_proxy::utilities::error_json::ErrorJson;
+use crate::http_proxy::utilities::ssh_keys;
 
 #[derive(Deserialize)]
 pub struct RequestPayload {
     device_id: String,
     session_type: String,
+    /// Lifetime of the session, capped to `MAX_SESSION_LIFETIME`.
+    expires_in_secs: Option<u64>,
+    /// Number of SSH or TTY connections the session can be used for.
+    max_uses: Option<u32>,
 }
 
 pub async fn request_session(
-    request: HttpRequest,
+    caller: Caller,
     context: Data<AppContext>,
+    config: Data<HttpProxyConfig>,
     body: Json<RequestPayload>,
 ) -> impl Responder {
-    let Some(jwt) = authorization::extract_authorization_token(&request) else {
-        return HttpResponse::Unauthorized().json(ErrorJson::from("Missing Authorization header"));
-    };
+    if let Err(resp) = caller.require(Role::Operator) {
+        return resp;
+    }
 
     let session_type = match RemoteAccessType::try_from(body.session_type.as_str()) {
         Ok(value) => value,
@@ -36,8 +44,46 @@ pub async fn request_session(
         }
     };
 
-    if let Err(error) =
-        handle_ssh_edgecase(context.clone(), &jwt, &body.device_id, session_type).await
+    let lifetime = body
+        .expires_in_secs
+        .map_or(DEFAULT_SESSION_LIFETIME, Duration::from_secs);
+
+    if lifetime.is_zero() || lifetime > MAX_SESSION_LIFETIME {
+        return HttpResponse::BadRequest().json(ErrorJson::from(format!(
+            "Session lifetime must be between 1 and {} seconds",
+            MAX_SESSION_LIFETIME.as_secs()
+        )));
+    }
+
+    if body.max_uses == Some(0) {
+        return HttpResponse::BadRequest().json(ErrorJson::from("max_uses must be positive"));
+    }
+
+    let Ok(device) = context
+        .datastore
+        .obtain_device_by_id(&caller.jwt, &body.device_id, false)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device record"));
+    };
+
+    let Some(device) = device else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Device not found"));
+    };
+
+    if let Err(resp) = caller.ensure_device_access(&device) {
+        return resp;
+    }
+
+    if let Err(error) = handle_ssh_edgecase(
+        context.clone(),
+        &caller.jwt,
+        &body.device_id,
+        session_type,
+        config.ssh_key_ttl,
+    )
+    .await
     {
         return HttpResponse::InternalServerError().json(ErrorJson::from(format!(
             "Failed to handle SSH keys: {}",
@@ -45,37 +91,45 @@ pub async fn request_session(
         )));
     }
 
-    let session = RemoteAccessSession::new(&body.device_id, session_type);
+    let session = RemoteAccessSession::new(
+        &body.device_id,
+        session_type,
+        Some(caller.account_id.clone()),
+        lifetime,
+        body.max_uses,
+    );
 
-    if let Err(error) = context.datastore.create_session(&jwt, &session).await {
+    if let Err(error) = context
+        .datastore
+        .create_session(&caller.jwt, &session)
+        .await
+    {
         return HttpResponse::InternalServerError().json(ErrorJson::from(format!(
             "Datastore operation failed: {}",
             error.to_str()
         )));
     }
 
-    HttpResponse::Created().json(json!({"session_token": session.token}))
+    HttpResponse::Created().json(json!({
+        "session_token": session.token,
+        "expires_at": session.expires_at,
+        "max_uses": session.max_uses,
+    }))
 }
 
+/// Makes sure the device has an SSH keypair that is not expired, rotating it otherwise.
 async fn handle_ssh_edgecase(
     context: Data<AppContext>,
     token: &str,
     device_id: &str,
     session_type: RemoteAccessType,
+    ttl: Duration,
 ) -> Result<(), Error> {
     if session_type != RemoteAccessType::Ssh {
         return Ok(());
     }
 
-    match context.datastore.obtain_ssh_keypair(token, device_id).await {
-        Ok(Some(_)) => {
-            // Future enhancement: Validate SSH key expiry or other constraints.
-            Ok(())
-        }
-        Ok(None) => {
-            let data = SSHKeypair::generate(device_id).await?;
-            context.datastore.create_ssh_keypair(token, &data).await
-        }
-        Err(err) => Err(err),
-    }
+    ssh_keys::ensure_keypair(&context, token, device_id, ttl)
+        .await
+        .map(|_| ())
 }
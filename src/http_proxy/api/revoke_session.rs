@@ -0,0 +1,77 @@
+use crate::app_context::AppContext;
+use crate::http_proxy::proxy::ConnectionPool;
+use crate::http_proxy::utilities::caller::{Caller, Role};
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use actix_web::HttpResponse;
+use actix_web::Responder;
+use actix_web::web::Data;
+use actix_web::web::Path;
+
+/// Revokes a remote access session before it expires.
+///
+/// Idle UI connections of the session are closed right away; SSH and TTY
+/// sessions already open are left running until they end.
+pub async fn revoke_session(
+    caller: Caller,
+    context: Data<AppContext>,
+    pool: Data<ConnectionPool>,
+    session_token: Path<String>,
+) -> impl Responder {
+    if let Err(resp) = caller.require(Role::Operator) {
+        return resp;
+    }
+
+    let Ok(session) = context
+        .datastore
+        .obtain_session(&caller.jwt, &session_token)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch session record"));
+    };
+
+    let Some(session) = session else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Session not found"));
+    };
+
+    let Ok(device) = context
+        .datastore
+        .obtain_device_by_id(&caller.jwt, &session.device_id, false)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device record"));
+    };
+
+    let Some(device) = device else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Session not found"));
+    };
+
+    if caller.ensure_device_access(&device).is_err() {
+        return HttpResponse::NotFound().json(ErrorJson::from("Session not found"));
+    }
+
+    // Admins may revoke the sessions of other accounts, e.g. to cut off a leaked one.
+    // Sessions without a recorded owner are left to admins.
+    let is_owner = session.owner.as_deref() == Some(caller.account_id.as_str());
+
+    if !is_owner && caller.require(Role::Admin).is_err() {
+        return HttpResponse::Forbidden()
+            .json(ErrorJson::from("Session belongs to another account"));
+    }
+
+    if let Err(error) = context
+        .datastore
+        .delete_session(&caller.jwt, &session.id)
+        .await
+    {
+        return HttpResponse::InternalServerError().json(ErrorJson::from(format!(
+            "Datastore operation failed: {}",
+            error.to_str()
+        )));
+    }
+
+    pool.evict(&session.token);
+
+    HttpResponse::NoContent().finish()
+}
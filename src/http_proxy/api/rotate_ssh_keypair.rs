@@ -0,0 +1,78 @@
+use crate::app_context::AppContext;
+use crate::http_proxy::config::HttpProxyConfig;
+use crate::http_proxy::utilities::caller::{Caller, Role};
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use crate::http_proxy::utilities::ssh_keys;
+use actix_web::HttpResponse;
+use actix_web::Responder;
+
+use actix_web::web::Data;
+use actix_web::web::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+pub struct RotateKeypairPayload {
+    device_id: String,
+}
+
+/// Replaces the SSH keypair of a device before it expires, e.g. after a suspected compromise.
+pub async fn rotate_ssh_keypair(
+    caller: Caller,
+    context: Data<AppContext>,
+    config: Data<HttpProxyConfig>,
+    body: Json<RotateKeypairPayload>,
+) -> impl Responder {
+    if let Err(resp) = caller.require(Role::Admin) {
+        return resp;
+    }
+
+    let Ok(device) = context
+        .datastore
+        .obtain_device_by_id(&caller.jwt, &body.device_id, false)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device record"));
+    };
+
+    let Some(device) = device else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Device not found"));
+    };
+
+    if let Err(resp) = caller.ensure_device_access(&device) {
+        return resp;
+    }
+
+    let Ok(previous) = context
+        .datastore
+        .obtain_ssh_keypair(&caller.jwt, &device.id)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch SSH keys"));
+    };
+
+    let keypair = match ssh_keys::rotate_keypair(
+        &context,
+        &caller.jwt,
+        &device.id,
+        previous,
+        config.ssh_key_ttl,
+    )
+    .await
+    {
+        Ok(keypair) => keypair,
+        Err(err) => {
+            return HttpResponse::InternalServerError().json(ErrorJson::from(format!(
+                "Failed to rotate SSH keys: {}",
+                err.to_str()
+            )));
+        }
+    };
+
+    HttpResponse::Ok().json(json!({
+        "created_at": keypair.created_at,
+        "expires_at": keypair.expires_at().map(|expires_at| expires_at.to_rfc3339()),
+    }))
+}
@@ -0,0 +1,110 @@
+use crate::app_context::AppContext;
+use crate::http_proxy::utilities::caller::{Caller, Role};
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use actix_web::HttpResponse;
+use actix_web::Responder;
+
+use actix_web::web::Data;
+use actix_web::web::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+/// Longest login user accepted, as with `useradd`.
+const MAX_LOGIN_USER_LEN: usize = 32;
+
+#[derive(Deserialize)]
+pub struct SshSettingsPayload {
+    device_id: String,
+    /// User the SSH gateway logs in as, `DEFAULT_SSH_LOGIN_USER` if unset.
+    login_user: Option<String>,
+    /// Forgets the pinned host key, e.g. after the device was reinstalled.
+    #[serde(default)]
+    reset_host_key: bool,
+}
+
+pub async fn update_device_ssh_settings(
+    caller: Caller,
+    context: Data<AppContext>,
+    body: Json<SshSettingsPayload>,
+) -> impl Responder {
+    if let Err(resp) = caller.require(Role::Admin) {
+        return resp;
+    }
+
+    let body = body.into_inner();
+
+    if !body.login_user.as_deref().is_none_or(is_valid_login_user) {
+        return HttpResponse::BadRequest().json(ErrorJson::from("Invalid login user"));
+    }
+
+    let Ok(device) = context
+        .datastore
+        .obtain_device_by_id(&caller.jwt, &body.device_id, false)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device record"));
+    };
+
+    let Some(mut device) = device else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Device not found"));
+    };
+
+    if let Err(resp) = caller.ensure_device_access(&device) {
+        return resp;
+    }
+
+    let Ok(keypair) = context
+        .datastore
+        .obtain_ssh_keypair(&caller.jwt, &device.id)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch SSH keys"));
+    };
+
+    device.ssh_login_user = body.login_user;
+
+    if context
+        .datastore
+        .update_device(&caller.jwt, &body.device_id, &device)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to update device"));
+    }
+
+    let mut host_key_fingerprint = keypair
+        .as_ref()
+        .and_then(|keypair| keypair.host_key_fingerprint.clone());
+
+    if let Some(keypair) = keypair.filter(|_| body.reset_host_key) {
+        if context
+            .datastore
+            .update_ssh_host_key_fingerprint(&caller.jwt, &keypair.id, None)
+            .await
+            .is_err()
+        {
+            return HttpResponse::InternalServerError()
+                .json(ErrorJson::from("Failed to reset SSH host key"));
+        }
+
+        host_key_fingerprint = None;
+    }
+
+    HttpResponse::Ok().json(json!({
+        "login_user": device.ssh_login_user(),
+        "host_key_fingerprint": host_key_fingerprint,
+    }))
+}
+
+/// Accepts portable user names: letters, digits, `.`, `_` and `-`, not starting with `-`.
+fn is_valid_login_user(user: &str) -> bool {
+    !user.is_empty()
+        && user.len() <= MAX_LOGIN_USER_LEN
+        && !user.starts_with('-')
+        && user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
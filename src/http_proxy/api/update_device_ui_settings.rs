@@ -0,0 +1,87 @@
+use crate::app_context::AppContext;
+use crate::datastore::UiProtocol;
+use crate::http_proxy::utilities::caller::{Caller, Role};
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use actix_web::HttpResponse;
+use actix_web::Responder;
+
+use actix_web::web::Data;
+use actix_web::web::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+/// Settings left unset fall back to their defaults, see `Device::ui_settings`.
+#[derive(Deserialize)]
+pub struct UiSettingsPayload {
+    device_id: String,
+    protocol: UiProtocol,
+    port: Option<u16>,
+    host: Option<String>,
+    tls_server_name: Option<String>,
+    /// Forgets the pinned certificate, e.g. after the device certificate was renewed.
+    #[serde(default)]
+    reset_certificate_pin: bool,
+}
+
+pub async fn update_device_ui_settings(
+    caller: Caller,
+    context: Data<AppContext>,
+    body: Json<UiSettingsPayload>,
+) -> impl Responder {
+    if let Err(resp) = caller.require(Role::Admin) {
+        return resp;
+    }
+
+    let body = body.into_inner();
+
+    if body.port == Some(0) {
+        return HttpResponse::BadRequest().json(ErrorJson::from("Invalid port"));
+    }
+
+    let Ok(device) = context
+        .datastore
+        .obtain_device_by_id(&caller.jwt, &body.device_id, false)
+        .await
+    else {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to fetch device record"));
+    };
+
+    let Some(mut device) = device else {
+        return HttpResponse::NotFound().json(ErrorJson::from("Device not found"));
+    };
+
+    if let Err(resp) = caller.ensure_device_access(&device) {
+        return resp;
+    }
+
+    let previous = device.ui_settings();
+
+    device.ui_protocol = Some(body.protocol);
+    device.ui_port = body.port;
+    device.ui_host = body.host;
+    device.ui_tls_server_name = body.tls_server_name;
+
+    let current = device.ui_settings();
+
+    // A different endpoint is expected to present a different certificate.
+    let endpoint_changed = previous.protocol != current.protocol
+        || previous.port != current.port
+        || previous.tls_server_name != current.tls_server_name;
+
+    if body.reset_certificate_pin || endpoint_changed {
+        device.ui_tls_certificate_fingerprint = None;
+    }
+
+    if context
+        .datastore
+        .update_device(&caller.jwt, &body.device_id, &device)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to update device"));
+    }
+
+    HttpResponse::Ok().json(json!(device.ui_settings()))
+}
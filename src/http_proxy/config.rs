@@ -1,16 +1,53 @@
+use crate::datastore::DEFAULT_SSH_KEY_TTL;
+use crate::http_proxy::utilities::caller::Role;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use rustls::ServerConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct HttpProxyConfig {
     pub(crate) addr: SocketAddr,
+    /// Whether references to the device address in HTML, CSS and JavaScript
+    /// bodies are rewritten to point to the proxy.
+    pub(crate) rewrite_bodies: bool,
+    /// How long idle browser connections are kept open.
+    pub(crate) keep_alive: Duration,
+    pub(crate) tls: Option<HttpProxyTlsConfig>,
+    /// Roles granted to the API callers, by the datastore role id of their account.
+    /// Callers whose role id is missing here have no role and are refused.
+    pub(crate) roles: HashMap<String, Role>,
+    /// How long the keypairs used to log into devices over SSH are used before being rotated.
+    pub(crate) ssh_key_ttl: Duration,
+}
+
+/// Locations of the PEM files used to serve the proxy over TLS.
+///
+/// The certificate must cover the session subdomains, e.g. `*.proxy.example.com`.
+#[derive(Debug, Clone)]
+pub struct HttpProxyTlsConfig {
+    pub(crate) cert: PathBuf,
+    pub(crate) key: PathBuf,
 }
 
 impl HttpProxyConfig {
     /// Constructs a `HttpProxyConfig` from the environment variables
-    /// `HTTP_PROXY_HOST` and `HTTP_PROXY_PORT`.
+    /// `HTTP_PROXY_HOST`, `HTTP_PROXY_PORT`, `HTTP_PROXY_REWRITE_BODIES`,
+    /// `HTTP_PROXY_KEEP_ALIVE_SECS` and `HTTP_PROXY_SSH_KEY_TTL_SECS`.
+    ///
+    /// The datastore role ids granted each role are listed, comma-separated, in
+    /// `HTTP_PROXY_VIEWER_ROLE_IDS`, `HTTP_PROXY_OPERATOR_ROLE_IDS` and `HTTP_PROXY_ADMIN_ROLE_IDS`.
     ///
-    /// Falls back to `Default` if either environment variable is missing or invalid.
+    /// Falls back to the `Default` values for missing or invalid variables.
+    /// TLS, with HTTP/2 support, is enabled when both `HTTP_PROXY_TLS_CERT`
+    /// and `HTTP_PROXY_TLS_KEY` are set.
     pub fn from_env() -> Self {
+        let mut config = Self::default();
+
         let host = std::env::var("HTTP_PROXY_HOST").ok();
         let port = std::env::var("HTTP_PROXY_PORT")
             .ok()
@@ -18,17 +55,95 @@ impl HttpProxyConfig {
 
         if let (Some(host), Some(port)) = (host, port) {
             if let Ok(addr) = format!("{}:{}", host, port).parse::<SocketAddr>() {
-                return Self { addr };
+                config.addr = addr;
+            }
+        }
+
+        if let Some(rewrite_bodies) = std::env::var("HTTP_PROXY_REWRITE_BODIES")
+            .ok()
+            .and_then(|v| v.parse::<bool>().ok())
+        {
+            config.rewrite_bodies = rewrite_bodies;
+        }
+
+        if let Some(secs) = std::env::var("HTTP_PROXY_KEEP_ALIVE_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            config.keep_alive = Duration::from_secs(secs);
+        }
+
+        // Listed from the lowest role up, so that an id listed twice gets the highest one.
+        for (variable, role) in [
+            ("HTTP_PROXY_VIEWER_ROLE_IDS", Role::Viewer),
+            ("HTTP_PROXY_OPERATOR_ROLE_IDS", Role::Operator),
+            ("HTTP_PROXY_ADMIN_ROLE_IDS", Role::Admin),
+        ] {
+            let ids = std::env::var(variable).unwrap_or_default();
+
+            for id in ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+                config.roles.insert(id.to_owned(), role);
             }
         }
 
-        Self::default()
+        if config.roles.is_empty() {
+            log::warn!("No API role ids are configured, every API call will be refused");
+        }
+
+        if let Some(secs) = std::env::var("HTTP_PROXY_SSH_KEY_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+        {
+            config.ssh_key_ttl = Duration::from_secs(secs);
+        }
+
+        let cert = std::env::var("HTTP_PROXY_TLS_CERT").ok();
+        let key = std::env::var("HTTP_PROXY_TLS_KEY").ok();
+
+        if let (Some(cert), Some(key)) = (cert, key) {
+            config.tls = Some(HttpProxyTlsConfig {
+                cert: cert.into(),
+                key: key.into(),
+            });
+        }
+
+        config
     }
 }
 
 impl Default for HttpProxyConfig {
     fn default() -> Self {
         let addr = "127.0.0.1:4444".parse().unwrap();
-        Self { addr }
+        Self {
+            addr,
+            rewrite_bodies: false,
+            keep_alive: Duration::from_secs(75),
+            tls: None,
+            roles: HashMap::new(),
+            ssh_key_ttl: DEFAULT_SSH_KEY_TTL,
+        }
+    }
+}
+
+impl HttpProxyTlsConfig {
+    /// Reads the configured certificate chain and key and builds the corresponding `ServerConfig`,
+    /// offering HTTP/2 to browsers.
+    pub fn load(&self) -> Result<ServerConfig, Error> {
+        let certs = CertificateDer::pem_file_iter(&self.cert)
+            .handle_err(location!())?
+            .collect::<Result<Vec<_>, _>>()
+            .handle_err(location!())?;
+
+        let key = PrivateKeyDer::from_pem_file(&self.key).handle_err(location!())?;
+
+        let mut config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .handle_err(location!())?;
+
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(config)
     }
 }
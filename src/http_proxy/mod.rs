@@ -4,8 +4,18 @@ use crate::http_proxy::api::enable_telemetry_monitoring;
 use crate::http_proxy::api::enable_traffic_monitoring;
 use actix_cors::Cors;
 use actix_web::{App, HttpServer, http, web};
+use api::apply_configuration;
 use api::authorize_device;
+use api::deauthorize_device;
+use api::get_device;
+use api::get_devices;
+use api::get_recording;
+use api::get_session_recordings;
 use api::request_session;
+use api::revoke_session;
+use api::rotate_ssh_keypair;
+use api::update_device_ssh_settings;
+use api::update_device_ui_settings;
 use config::HttpProxyConfig;
 
 mod api;
@@ -19,9 +29,13 @@ pub async fn run_http_proxy(context: AppContext) {
     let config = HttpProxyConfig::from_env();
     log::info!("HTTP proxy listening on {}", config.addr);
 
+    let pool = web::Data::new(proxy::ConnectionPool::new(context.shutdown.clone()));
+    let proxy_config = web::Data::new(config.clone());
     let context = web::Data::new(context);
 
-    HttpServer::new(move || {
+    let shutdown = context.shutdown.clone();
+
+    let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allowed_methods(vec!["GET", "POST", "DELETE", "PUT"])
@@ -33,15 +47,38 @@ pub async fn run_http_proxy(context: AppContext) {
 
         App::new()
             .app_data(context.clone())
+            .app_data(pool.clone())
+            .app_data(proxy_config.clone())
             .wrap(cors)
             .route(
                 "/wallguard/api/v1/remote_access",
                 web::post().to(request_session),
             )
+            .route(
+                "/wallguard/api/v1/remote_access/{token}",
+                web::delete().to(revoke_session),
+            )
+            .route(
+                "/wallguard/api/v1/remote_access/{token}/recordings",
+                web::get().to(get_session_recordings),
+            )
+            .route("/wallguard/api/v1/devices", web::get().to(get_devices))
+            .route(
+                "/wallguard/api/v1/devices/{device_id}",
+                web::get().to(get_device),
+            )
+            .route(
+                "/wallguard/api/v1/recordings/{recording_id}",
+                web::get().to(get_recording),
+            )
             .route(
                 "/wallguard/api/v1/authorize_device",
                 web::post().to(authorize_device),
             )
+            .route(
+                "/wallguard/api/v1/deauthorize_device",
+                web::post().to(deauthorize_device),
+            )
             .route(
                 "/wallguard/api/v1/enable_traffic_monitoring",
                 web::post().to(enable_traffic_monitoring),
@@ -54,6 +91,22 @@ pub async fn run_http_proxy(context: AppContext) {
                 "/wallguard/api/v1/enable_config_monitoring",
                 web::post().to(enable_config_monitoring),
             )
+            .route(
+                "/wallguard/api/v1/apply_configuration",
+                web::post().to(apply_configuration),
+            )
+            .route(
+                "/wallguard/api/v1/update_device_ui_settings",
+                web::post().to(update_device_ui_settings),
+            )
+            .route(
+                "/wallguard/api/v1/update_device_ssh_settings",
+                web::post().to(update_device_ssh_settings),
+            )
+            .route(
+                "/wallguard/api/v1/rotate_ssh_keypair",
+                web::post().to(rotate_ssh_keypair),
+            )
             .route(
                 "/wallguard/gateway/ssh",
                 web::to(ssh_gateway::open_ssh_session),
@@ -64,9 +117,36 @@ pub async fn run_http_proxy(context: AppContext) {
             )
             .default_service(web::to(proxy::proxy_http_request))
     })
-    .bind(config.addr)
-    .unwrap()
-    .run()
-    .await
-    .unwrap()
+    .disable_signals()
+    .keep_alive(config.keep_alive);
+
+    let server = match &config.tls {
+        Some(tls) => {
+            let tls = match tls.load() {
+                Ok(tls) => tls,
+                Err(err) => {
+                    log::error!(
+                        "Failed to load HTTP proxy TLS configuration: {}",
+                        err.to_str()
+                    );
+                    std::process::exit(1);
+                }
+            };
+
+            server.bind_rustls_0_23(config.addr, tls)
+        }
+        None => server.bind(config.addr),
+    };
+
+    let server = server.unwrap().run();
+
+    // Only stop accepting new connections: the workers keep running, so that
+    // WebSocket relays can finish during the shutdown grace period.
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown.triggered().await;
+        handle.pause().await;
+    });
+
+    server.await.unwrap()
 }
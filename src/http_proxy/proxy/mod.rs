@@ -1,6 +1,5 @@
 //! TODO:
 //! - Fetch session and related device in 1 Datastore query
-//! - Use actual device HTTP protocol (http vs https)
 
 use actix_web::HttpRequest;
 use actix_web::HttpResponse;
@@ -9,15 +8,27 @@ use actix_web::web::{Data, Payload};
 
 use crate::app_context::AppContext;
 use crate::datastore::RemoteAccessType;
+use crate::http_proxy::config::HttpProxyConfig;
 use crate::http_proxy::utilities::error_json::ErrorJson;
 use crate::http_proxy::utilities::request_handling;
 use crate::http_proxy::utilities::tunneling;
 
+pub(crate) use pool::ConnectionPool;
+use pool::PooledConnection;
+use rewrite::Rewriter;
+
+mod pool;
 mod request;
+mod rewrite;
+mod sender;
+mod tls;
+mod upgrade;
 
 pub async fn proxy_http_request(
     request: HttpRequest,
     context: Data<AppContext>,
+    pool: Data<ConnectionPool>,
+    config: Data<HttpProxyConfig>,
     body: Payload,
 ) -> impl Responder {
     log::info!("Proxy request: {request:?}");
@@ -61,13 +72,101 @@ pub async fn proxy_http_request(
         return HttpResponse::NotFound().json(ErrorJson::from("Device is unauthorized"));
     }
 
-    let protocol = "http";
+    let settings = device.ui_settings();
 
-    let Ok(stream) = tunneling::establish_tunneled_ui(&context, &device.uuid, protocol).await
-    else {
-        return HttpResponse::InternalServerError()
-            .json(ErrorJson::from("Failed to establish a tunnel"));
+    let rewriter = {
+        let connection_info = request.connection_info();
+        Rewriter::new(
+            connection_info.scheme(),
+            connection_info.host(),
+            &settings,
+            config.rewrite_bodies,
+        )
+    };
+
+    // Upgrades are only defined for HTTP/1.1, and take over the connection.
+    let is_upgrade = upgrade::is_upgrade(&request);
+
+    let connection = pool
+        .checkout(&session_token)
+        .filter(|connection| !is_upgrade || !connection.sender.is_multiplexed());
+
+    let mut connection = match connection {
+        Some(connection) => connection,
+        None => {
+            let Ok((stream, tunnel)) =
+                tunneling::establish_tunneled_ui(&context, &device.uuid, &settings).await
+            else {
+                return HttpResponse::InternalServerError()
+                    .json(ErrorJson::from("Failed to establish a tunnel"));
+            };
+
+            let sender = match request::connect(stream, &settings, !is_upgrade).await {
+                Ok((sender, fingerprint)) => {
+                    let unpinned =
+                        fingerprint.filter(|_| settings.certificate_fingerprint.is_none());
+
+                    if let Some(fingerprint) = unpinned {
+                        pin_certificate(&context, &device.id, &fingerprint).await;
+                    }
+                    sender
+                }
+                Err(resp) => return resp,
+            };
+
+            let connection = PooledConnection::new(sender, tunnel);
+
+            // Concurrent requests of the session can share the connection right away.
+            if let Some(shared) = connection.try_clone() {
+                pool.checkin(&session_token, shared);
+            }
+
+            connection
+        }
+    };
+
+    if is_upgrade {
+        let PooledConnection { sender, tunnel, .. } = connection;
+        return upgrade::proxy_upgrade(sender, tunnel, request, body, &settings.host, &rewriter)
+            .await;
+    }
+
+    let response = tokio::select! {
+        response = request::proxy_request(&mut connection.sender, request, body, &settings.host, &rewriter) => response,
+        _ = connection.tunnel.closed() => {
+            return HttpResponse::ServiceUnavailable().json(ErrorJson::from("Tunnel closed"));
+        }
+    };
+
+    let response = match response {
+        Ok(response) => response,
+        Err(resp) => return resp,
+    };
+
+    let tunnel_closed = connection.tunnel.closed();
+    let pool = pool.clone();
+
+    request::stream_response(response, &rewriter, tunnel_closed, move || {
+        pool.checkin(&session_token, connection)
+    })
+}
+
+/// Records the certificate presented by a device UI on first use.
+async fn pin_certificate(context: &AppContext, device_id: &str, fingerprint: &str) {
+    let Ok(token) = context.sysdev_token_provider.get().await else {
+        log::error!("Failed to pin UI certificate of device {device_id}: no sysdev token");
+        return;
     };
 
-    request::proxy_request(request, body, "domain.com", false, stream).await
+    match context
+        .datastore
+        .update_device_ui_certificate_fingerprint(&token.jwt, device_id, Some(fingerprint))
+        .await
+    {
+        Ok(_) => log::info!("Pinned UI certificate {fingerprint} of device {device_id}"),
+        Err(err) => log::error!(
+            "Failed to pin UI certificate of device {device_id}: {}",
+            err.to_str()
+        ),
+    }
 }
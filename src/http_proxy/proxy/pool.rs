@@ -0,0 +1,155 @@
+//! Pool of established connections to device web UIs, keyed by remote access session token.
+//!
+//! Loading a device web UI takes dozens of requests. Instead of opening a tunnel and
+//! performing the HTTP handshake for each of them, connections are handed back to the pool
+//! once a response has been fully received, and reused by the next request of the same session.
+//! Connections idle for longer than `IDLE_TIMEOUT` are dropped, closing their tunnel.
+//!
+//! HTTP/2 connections carry concurrent requests: they stay in the pool while in use,
+//! and each request gets its own handle to the connection.
+
+use super::sender::RequestSender;
+use crate::orchestrator::TunnelGuard;
+use crate::shutdown::ShutdownSignal;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Time after which an unused connection is dropped.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of idle connections kept per session.
+const MAX_IDLE_PER_SESSION: usize = 8;
+
+/// Interval between two sweeps of expired connections.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An HTTP connection to a device together with the tunnel carrying it.
+#[derive(Debug)]
+pub(crate) struct PooledConnection {
+    pub(crate) sender: RequestSender,
+    pub(crate) tunnel: Arc<TunnelGuard>,
+    idle_since: Instant,
+}
+
+impl PooledConnection {
+    pub fn new(sender: RequestSender, tunnel: TunnelGuard) -> Self {
+        Self {
+            sender,
+            tunnel: Arc::new(tunnel),
+            idle_since: Instant::now(),
+        }
+    }
+
+    /// Returns another handle to the connection, if it carries concurrent requests.
+    pub fn try_clone(&self) -> Option<Self> {
+        Some(Self {
+            sender: self.sender.try_clone()?,
+            tunnel: self.tunnel.clone(),
+            idle_since: Instant::now(),
+        })
+    }
+
+    fn is_usable(&self) -> bool {
+        !self.sender.is_closed()
+            && !self.tunnel.is_closed()
+            && self.idle_since.elapsed() < IDLE_TIMEOUT
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnectionPool {
+    idle: Arc<Mutex<HashMap<String, Vec<PooledConnection>>>>,
+}
+
+impl ConnectionPool {
+    /// Creates a new pool and starts the task dropping expired connections.
+    ///
+    /// All connections are dropped once the shutdown is triggered.
+    pub fn new(shutdown: ShutdownSignal) -> Self {
+        let pool = Self::default();
+
+        let sweeper = pool.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(SWEEP_INTERVAL) => sweeper.sweep(),
+                    _ = shutdown.triggered() => {
+                        sweeper.idle.lock().unwrap().clear();
+                        return;
+                    }
+                }
+            }
+        });
+
+        pool
+    }
+
+    /// Takes an idle connection of the given session, if any is still usable.
+    ///
+    /// Multiplexed connections are shared rather than taken.
+    pub fn checkout(&self, session: &str) -> Option<PooledConnection> {
+        let mut idle = self.idle.lock().unwrap();
+        let connections = idle.get_mut(session)?;
+
+        connections.retain(PooledConnection::is_usable);
+
+        let shared = connections
+            .iter_mut()
+            .find(|connection| connection.sender.is_multiplexed())
+            .and_then(|connection| {
+                connection.idle_since = Instant::now();
+                connection.try_clone()
+            });
+
+        let connection = shared.or_else(|| connections.pop());
+
+        if connections.is_empty() {
+            idle.remove(session);
+        }
+
+        connection
+    }
+
+    /// Hands a connection back to the pool once its last response has been fully received.
+    ///
+    /// A multiplexed connection is only added if the session has none yet, as it is
+    /// otherwise a handle to the one already pooled.
+    pub fn checkin(&self, session: &str, mut connection: PooledConnection) {
+        if connection.sender.is_closed() || connection.tunnel.is_closed() {
+            return;
+        }
+
+        connection.idle_since = Instant::now();
+
+        let mut idle = self.idle.lock().unwrap();
+        let connections = idle.entry(session.to_string()).or_default();
+
+        if connection.sender.is_multiplexed()
+            && connections
+                .iter()
+                .any(|pooled| pooled.sender.is_multiplexed() && pooled.is_usable())
+        {
+            return;
+        }
+
+        if connections.len() < MAX_IDLE_PER_SESSION {
+            connections.push(connection);
+        }
+    }
+
+    /// Drops the idle connections of the given session, closing their tunnels.
+    pub fn evict(&self, session: &str) {
+        self.idle.lock().unwrap().remove(session);
+    }
+
+    fn sweep(&self) {
+        let mut idle = self.idle.lock().unwrap();
+
+        for connections in idle.values_mut() {
+            connections.retain(PooledConnection::is_usable);
+        }
+
+        idle.retain(|_, connections| !connections.is_empty());
+    }
+}
@@ -1,169 +1,443 @@
 use actix_web::Error as ActixError;
 use actix_web::HttpRequest as ActixRequest;
 use actix_web::HttpResponse as ActixResponse;
+use actix_web::HttpResponseBuilder;
 use actix_web::Result as ActixResult;
+use actix_web::body::SizedStream;
 use actix_web::error::ErrorInternalServerError as InternalServerError;
 use actix_web::http::Method as ActixMethod;
 use actix_web::http::StatusCode as ActixStatus;
 use actix_web::web::Payload as ActixBody;
-use http_body_util::BodyExt;
-use http_body_util::Full as BodyWrapper;
+use futures_util::{Stream, StreamExt};
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyDataStream, BodyExt, Empty, StreamBody};
 use hyper::Method as HyperMethod;
 use hyper::Request as HyperRequest;
 use hyper::Response as HyperResponse;
 use hyper::body::Bytes as HyperBody;
+use hyper::body::Frame as HyperFrame;
 use hyper::body::Incoming;
+use hyper::client::conn::{http1, http2};
 use hyper::header::HeaderName as HyperHeaderName;
 use hyper::header::HeaderValue as HyperHeaderValue;
+use hyper::http::method::InvalidMethod;
 use hyper::rt::{Read, Write};
-use hyper_util::rt::TokioIo;
-use nullnet_liberror::{Error, ErrorHandler, Location, location};
-use rustls::pki_types::ServerName;
-use rustls::{ClientConfig, RootCertStore};
-use std::sync::Arc;
-use tokio::net::TcpStream;
-use tokio_rustls::TlsConnector;
-use tokio_rustls::client::TlsStream;
-use webpki_roots::TLS_SERVER_ROOTS;
-
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
+
+use super::rewrite::Rewriter;
+use super::sender::RequestSender;
+use super::tls::{self, HandshakeError};
+use crate::datastore::{DeviceUiSettings, UiProtocol};
 use crate::http_proxy::utilities::error_json::ErrorJson;
+use crate::reverse_tunnel::TunnelStream;
+
+/// Number of request body chunks buffered between the client and the device.
+///
+/// Once the buffer is full, the client upload is paused until the device catches up.
+const REQUEST_BODY_BUFFER: usize = 16;
+
+/// Headers describing a single connection, which must not be forwarded.
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// ALPN protocols offered to devices, preferring HTTP/2.
+const ALPN_HTTP2: &[&[u8]] = &[b"h2", b"http/1.1"];
+const ALPN_HTTP1: &[&[u8]] = &[b"http/1.1"];
+
+/// Body of the requests forwarded to a device.
+pub(crate) type RequestBody = UnsyncBoxBody<HyperBody, io::Error>;
+
+/// Returns `true` if the header only applies to the current connection, either because it
+/// is hop-by-hop by definition, or because it is listed in the `Connection` header.
+fn is_hop_by_hop(name: &str, connection: Option<&str>) -> bool {
+    HOP_BY_HOP_HEADERS.contains(&name)
+        || connection.is_some_and(|connection| {
+            connection
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case(name))
+        })
+}
 
-async fn handshake(
-    tcp_stream: TcpStream,
-    server_name: impl Into<String>,
-) -> Result<TlsStream<TcpStream>, Error> {
-    let mut root_store = RootCertStore::empty();
-    root_store.extend(TLS_SERVER_ROOTS.iter().map(|ta| ta.to_owned()));
+/// Converts the method as is, including extension methods such as WebDAV's `PROPFIND`.
+fn convert_method(method: &ActixMethod) -> Result<HyperMethod, InvalidMethod> {
+    HyperMethod::from_bytes(method.as_str().as_bytes())
+}
 
-    let config = ClientConfig::builder()
-        .with_root_certificates(root_store)
-        .with_no_client_auth();
+/// Body forwarded to the device: the client payload if the request carries one.
+fn request_body(request: &ActixRequest, body: ActixBody) -> RequestBody {
+    // HTTP/2 requests may have a body without announcing its length.
+    let may_have_unannounced_body = request.version() == actix_web::http::Version::HTTP_2
+        && !matches!(*request.method(), ActixMethod::GET | ActixMethod::HEAD);
+
+    let has_body = request
+        .headers()
+        .contains_key(hyper::header::CONTENT_LENGTH)
+        || request
+            .headers()
+            .contains_key(hyper::header::TRANSFER_ENCODING)
+        || may_have_unannounced_body;
+
+    if has_body {
+        stream_request_body(body)
+    } else {
+        empty_body()
+    }
+}
 
-    let connector = TlsConnector::from(Arc::new(config));
+pub(super) fn empty_body() -> RequestBody {
+    Empty::new().map_err(|never| match never {}).boxed_unsync()
+}
 
-    let domain = ServerName::try_from(server_name.into()).handle_err(location!())?;
+/// Relays the client request body to the device as it arrives.
+fn stream_request_body(mut body: ActixBody) -> RequestBody {
+    let (tx, rx) = mpsc::channel(REQUEST_BODY_BUFFER);
 
-    let tls_stream = connector
-        .connect(domain, tcp_stream)
-        .await
-        .handle_err(location!())?;
+    // The payload is bound to the worker thread, so it is pumped by a local task.
+    actix_web::rt::spawn(async move {
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|err| io::Error::other(err.to_string()));
+            let failed = chunk.is_err();
 
-    Ok(tls_stream)
-}
+            if tx.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    });
 
-fn convert_method(method: &ActixMethod) -> HyperMethod {
-    match *method {
-        ActixMethod::CONNECT => HyperMethod::CONNECT,
-        ActixMethod::DELETE => HyperMethod::DELETE,
-        ActixMethod::GET => HyperMethod::GET,
-        ActixMethod::HEAD => HyperMethod::HEAD,
-        ActixMethod::OPTIONS => HyperMethod::OPTIONS,
-        ActixMethod::PATCH => HyperMethod::PATCH,
-        ActixMethod::POST => HyperMethod::POST,
-        ActixMethod::PUT => HyperMethod::PUT,
-        ActixMethod::TRACE => HyperMethod::TRACE,
-        _ => HyperMethod::GET,
-    }
+    let stream = ReceiverStream::new(rx).map(|chunk| chunk.map(HyperFrame::data));
+
+    StreamBody::new(stream).boxed_unsync()
 }
 
-async fn convert_request(
-    request: ActixRequest,
-    body: ActixBody,
-    domain: &str,
-) -> ActixResult<HyperRequest<BodyWrapper<HyperBody>>> {
+pub(super) fn convert_request(
+    request: &ActixRequest,
+    body: RequestBody,
+    host: &str,
+    rewriter: &Rewriter,
+) -> ActixResult<HyperRequest<RequestBody>> {
     let uri: hyper::Uri = request
         .uri()
         .to_string()
         .parse()
         .map_err(InternalServerError)?;
 
-    let method = convert_method(request.method());
+    let method = convert_method(request.method()).map_err(InternalServerError)?;
 
     let mut request_builder = hyper::Request::builder().method(method).uri(uri);
 
+    let connection = request
+        .headers()
+        .get(hyper::header::CONNECTION)
+        .and_then(|value| value.to_str().ok());
+
     for (header_name, header_value) in request.headers() {
         if header_name.as_str() == "host" || header_name.as_str() == "referer" {
             continue;
         }
 
+        if is_hop_by_hop(header_name.as_str(), connection) {
+            continue;
+        }
+
+        if header_name.as_str() == "accept-encoding" && rewriter.rewrites_bodies() {
+            continue;
+        }
+
+        let header_value = match header_value.to_str().ok() {
+            Some(origin) if header_name.as_str() == "origin" => rewriter
+                .request_origin(origin)
+                .map_or(header_value.as_bytes(), str::as_bytes),
+            _ => header_value.as_bytes(),
+        };
+
         if let (Ok(name), Ok(value)) = (
             HyperHeaderName::from_bytes(header_name.as_str().as_bytes()),
-            HyperHeaderValue::from_bytes(header_value.as_bytes()),
+            HyperHeaderValue::from_bytes(header_value),
         ) {
             request_builder = request_builder.header(name, value);
         }
     }
 
-    request_builder = request_builder.header(hyper::header::HOST, domain);
+    request_builder = request_builder.header(hyper::header::HOST, host);
+
+    // The upgrade is requested from the device on behalf of the client.
+    let upgrade = request
+        .headers()
+        .get(hyper::header::UPGRADE)
+        .filter(|_| request.head().upgrade());
+
+    if let Some(upgrade) = upgrade {
+        request_builder = request_builder
+            .header(hyper::header::CONNECTION, "upgrade")
+            .header(hyper::header::UPGRADE, upgrade.as_bytes());
+    }
 
-    let body = BodyWrapper::new(body.to_bytes().await?);
+    // Compressed bodies can't be rewritten.
+    if rewriter.rewrites_bodies() {
+        request_builder = request_builder.header(hyper::header::ACCEPT_ENCODING, "identity");
+    }
 
     let request = request_builder.body(body).map_err(InternalServerError)?;
 
     Ok(request)
 }
 
-async fn convert_response(
-    mut response: HyperResponse<Incoming>,
+/// Streams the body of a device response to the client.
+///
+/// `on_complete` runs once the body has been fully received, and the stream fails
+/// as soon as `tunnel_closed` resolves.
+pub(super) struct ResponseStream {
+    body: Pin<Box<dyn Stream<Item = io::Result<HyperBody>>>>,
+    tunnel_closed: Pin<Box<dyn Future<Output = ()>>>,
+    on_complete: Option<Box<dyn FnOnce()>>,
+}
+
+impl ResponseStream {
+    pub(super) fn new(
+        body: impl Stream<Item = io::Result<HyperBody>> + 'static,
+        tunnel_closed: impl Future<Output = ()> + 'static,
+        on_complete: impl FnOnce() + 'static,
+    ) -> Self {
+        Self {
+            body: Box::pin(body),
+            tunnel_closed: Box::pin(tunnel_closed),
+            on_complete: Some(Box::new(on_complete)),
+        }
+    }
+}
+
+impl Stream for ResponseStream {
+    type Item = Result<HyperBody, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.tunnel_closed.as_mut().poll(cx).is_ready() {
+            this.on_complete = None;
+            return Poll::Ready(Some(Err(io::Error::other("Tunnel closed"))));
+        }
+
+        match this.body.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(chunk))) => Poll::Ready(Some(Ok(chunk))),
+            Poll::Ready(Some(Err(err))) => {
+                this.on_complete = None;
+                Poll::Ready(Some(Err(err)))
+            }
+            Poll::Ready(None) => {
+                if let Some(on_complete) = this.on_complete.take() {
+                    on_complete();
+                }
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Copies the end-to-end headers of a device response, except its length, which actix
+/// sets for the relayed body.
+///
+/// Headers referring to the device address are rewritten to refer to the proxy.
+pub(super) fn copy_response_headers(
+    response_builder: &mut HttpResponseBuilder,
+    headers: &hyper::HeaderMap,
+    rewriter: &Rewriter,
+) {
+    let connection = headers
+        .get(hyper::header::CONNECTION)
+        .and_then(|value| value.to_str().ok());
+
+    for (name, value) in headers.iter() {
+        if name == hyper::header::CONTENT_LENGTH || is_hop_by_hop(name.as_str(), connection) {
+            continue;
+        }
+
+        let rewritten = value.to_str().ok().and_then(|value| match *name {
+            hyper::header::LOCATION | hyper::header::CONTENT_LOCATION => Some(rewriter.url(value)),
+            hyper::header::REFRESH => Some(rewriter.refresh(value)),
+            hyper::header::SET_COOKIE => Some(rewriter.set_cookie(value)),
+            _ => None,
+        });
+
+        match rewritten {
+            Some(value) => response_builder.append_header((name.as_str(), value)),
+            None => response_builder.append_header((name.as_str(), value.as_bytes())),
+        };
+    }
+}
+
+fn convert_response(
+    response: HyperResponse<Incoming>,
+    rewriter: &Rewriter,
+    tunnel_closed: impl Future<Output = ()> + 'static,
+    on_complete: impl FnOnce() + 'static,
 ) -> Result<ActixResponse, ActixError> {
     let response_status =
         ActixStatus::from_u16(response.status().as_u16()).map_err(InternalServerError)?;
 
     let mut response_builder = actix_web::HttpResponse::build(response_status);
 
-    for (name, value) in response.headers().iter() {
-        response_builder.insert_header((name.as_str(), value.as_bytes()));
-    }
+    copy_response_headers(&mut response_builder, response.headers(), rewriter);
 
-    let mut data = vec![];
+    let rewrite_body = rewriter.should_rewrite_body(response.headers());
 
-    while let Some(next) = response.body_mut().frame().await {
-        let frame = next.map_err(InternalServerError)?;
+    // The length of a rewritten body isn't known in advance.
+    let content_length = response
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|_| !rewrite_body);
 
-        if let Some(chunk) = frame.data_ref() {
-            data.extend_from_slice(chunk.iter().as_slice());
-        }
-    }
+    let body =
+        BodyDataStream::new(response.into_body()).map(|chunk| chunk.map_err(io::Error::other));
 
-    Ok(response_builder.body(data))
-}
+    let stream = if rewrite_body {
+        let body = rewriter.body().rewrite_stream(body);
+        ResponseStream::new(body, tunnel_closed, on_complete)
+    } else {
+        ResponseStream::new(body, tunnel_closed, on_complete)
+    };
 
-pub async fn proxy_request(
-    request: ActixRequest,
-    body: ActixBody,
-    domain: &str,
-    is_https: bool,
-    stream: TcpStream,
-) -> ActixResponse {
-    let Ok(request) = convert_request(request, body, domain).await else {
-        return ActixResponse::InternalServerError().into();
+    let response = match content_length {
+        Some(length) => response_builder.body(SizedStream::new(length, stream)),
+        None => response_builder.streaming(stream),
     };
 
+    Ok(response)
+}
+
+/// Performs the TLS (if the UI is served over HTTPS) and HTTP handshakes over a tunneled stream.
+///
+/// HTTP/2 is used if `allow_http2` is set and the device selects it during the TLS handshake,
+/// HTTP/1.1 otherwise. Also returns the fingerprint of the certificate presented by the device,
+/// if any.
+pub async fn connect(
+    stream: TunnelStream,
+    settings: &DeviceUiSettings,
+    allow_http2: bool,
+) -> Result<(RequestSender, Option<String>), ActixResponse> {
     trait ReadWrite: Read + Write {}
     impl<T: Read + Write> ReadWrite for T {}
 
-    let io: Box<dyn ReadWrite + Send + Unpin> = if is_https {
-        let Ok(tls_stream) = handshake(stream, domain).await else {
-            return ActixResponse::ServiceUnavailable().json(ErrorJson::from("Handshake failed"));
+    let alpn = if allow_http2 { ALPN_HTTP2 } else { ALPN_HTTP1 };
+
+    let (io, fingerprint, http2): (Box<dyn ReadWrite + Send + Unpin>, _, _) = match settings
+        .protocol
+    {
+        UiProtocol::Https => {
+            let handshake = tls::handshake(
+                stream,
+                &settings.tls_server_name,
+                settings.certificate_fingerprint.as_deref(),
+                alpn,
+            )
+            .await;
+
+            match handshake {
+                Ok((tls_stream, fingerprint)) => {
+                    let http2 = tls_stream.get_ref().1.alpn_protocol() == Some(b"h2");
+                    (Box::new(TokioIo::new(tls_stream)), Some(fingerprint), http2)
+                }
+                Err(HandshakeError::CertificateMismatch {
+                    expected,
+                    presented,
+                }) => {
+                    log::warn!(
+                        "Device UI presented certificate {presented}, expected pinned certificate {expected}"
+                    );
+
+                    return Err(ActixResponse::BadGateway().json(ErrorJson::from(
+                        "The device presented a certificate different from the pinned one",
+                    )));
+                }
+                Err(HandshakeError::Failed(_)) => {
+                    return Err(ActixResponse::ServiceUnavailable()
+                        .json(ErrorJson::from("Handshake failed")));
+                }
+            }
+        }
+        UiProtocol::Http => (Box::new(TokioIo::new(stream)), None, false),
+    };
+
+    let sender = if http2 {
+        let Ok((sender, conn)) = http2::handshake(TokioExecutor::new(), io).await else {
+            return Err(ActixResponse::ServiceUnavailable().into());
         };
-        Box::new(TokioIo::new(tls_stream))
+
+        tokio::spawn(conn);
+        RequestSender::Http2(sender)
     } else {
-        Box::new(TokioIo::new(stream))
+        let Ok((sender, conn)) = http1::handshake(io).await else {
+            return Err(ActixResponse::ServiceUnavailable().into());
+        };
+
+        tokio::spawn(conn.with_upgrades());
+        RequestSender::Http1(sender)
     };
 
-    let Ok((mut sender, conn)) = hyper::client::conn::http1::handshake(io).await else {
-        return ActixResponse::ServiceUnavailable().into();
+    Ok((sender, fingerprint))
+}
+
+/// Forwards a request over an established connection.
+///
+/// Only the response head is awaited, the body is left to `stream_response`.
+pub async fn proxy_request(
+    sender: &mut RequestSender,
+    request: ActixRequest,
+    body: ActixBody,
+    host: &str,
+    rewriter: &Rewriter,
+) -> Result<HyperResponse<Incoming>, ActixResponse> {
+    let body = request_body(&request, body);
+
+    let Ok(request) = convert_request(&request, body, host, rewriter) else {
+        return Err(ActixResponse::InternalServerError().into());
     };
 
-    tokio::spawn(conn);
+    send_request(sender, request).await
+}
+
+pub(super) async fn send_request(
+    sender: &mut RequestSender,
+    request: HyperRequest<RequestBody>,
+) -> Result<HyperResponse<Incoming>, ActixResponse> {
+    if sender.ready().await.is_err() {
+        return Err(ActixResponse::ServiceUnavailable().into());
+    }
 
     let Ok(response) = sender.send_request(request).await else {
-        return ActixResponse::ServiceUnavailable().into();
+        return Err(ActixResponse::ServiceUnavailable().into());
     };
 
-    let Ok(response) = convert_response(response).await else {
-        return ActixResponse::InternalServerError().into();
-    };
+    Ok(response)
+}
 
-    response
+/// Streams a device response back to the client.
+///
+/// `on_complete` runs once the response body has been fully received, at which point the
+/// connection can serve another request. The body is cut short if `tunnel_closed`
+/// resolves before that.
+pub fn stream_response(
+    response: HyperResponse<Incoming>,
+    rewriter: &Rewriter,
+    tunnel_closed: impl Future<Output = ()> + 'static,
+    on_complete: impl FnOnce() + 'static,
+) -> ActixResponse {
+    convert_response(response, rewriter, tunnel_closed, on_complete)
+        .unwrap_or_else(|_| ActixResponse::InternalServerError().into())
 }
@@ -0,0 +1,382 @@
+//! Rewriting of device responses, so that navigation stays within the proxy.
+//!
+//! Device web UIs refer to themselves by the address they are configured with: redirects
+//! point to it, cookies are scoped to it and pages link to it. The proxy serves them from
+//! `<session>.<proxy-domain>` instead, so these references are mapped back onto the proxy:
+//! - `Location`, `Content-Location` and `Refresh` headers,
+//! - the `Domain` (and, over plain HTTP, `Secure`) attributes of `Set-Cookie` headers,
+//! - optionally, references in HTML, CSS and JavaScript bodies.
+//!
+//! Paths are left untouched, as the proxy maps them 1:1.
+
+use crate::datastore::{DeviceUiSettings, UiProtocol};
+use futures_util::{Stream, StreamExt};
+use hyper::HeaderMap;
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use std::io;
+
+/// Content types whose bodies may reference the device address.
+const REWRITTEN_CONTENT_TYPES: [&str; 4] = [
+    "text/html",
+    "text/css",
+    "application/javascript",
+    "text/javascript",
+];
+
+#[derive(Debug, Clone)]
+pub(crate) struct Rewriter {
+    /// Replacements from the device addresses to the proxy, longest pattern first.
+    replacements: Vec<(String, String)>,
+    device_origin: String,
+    proxy_origin: String,
+    proxy_is_secure: bool,
+    rewrite_bodies: bool,
+}
+
+impl Rewriter {
+    /// Creates a rewriter mapping the device described by `settings`
+    /// onto the proxy reached with `proxy_scheme` and `proxy_host`.
+    pub fn new(
+        proxy_scheme: &str,
+        proxy_host: &str,
+        settings: &DeviceUiSettings,
+        rewrite_bodies: bool,
+    ) -> Self {
+        let proxy_origin = format!("{proxy_scheme}://{proxy_host}");
+
+        let mut device_hosts = vec![settings.host.clone()];
+        let with_port = format!("{}:{}", settings.tls_server_name, settings.port);
+
+        for host in [with_port, settings.tls_server_name.clone()] {
+            let is_default_port =
+                !host.contains(':') && settings.port == settings.protocol.default_port();
+
+            if !device_hosts.contains(&host) && (host.contains(':') || is_default_port) {
+                device_hosts.push(host);
+            }
+        }
+
+        let mut replacements = Vec::new();
+
+        for host in &device_hosts {
+            for protocol in [UiProtocol::Https, UiProtocol::Http] {
+                let origin = format!("{}://{host}", protocol.as_str());
+                replacements.push((origin, proxy_origin.clone()));
+            }
+
+            replacements.push((format!("//{host}"), format!("//{proxy_host}")));
+        }
+
+        replacements.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.len()));
+
+        Self {
+            replacements,
+            device_origin: format!("{}://{}", settings.protocol.as_str(), settings.host),
+            proxy_origin,
+            proxy_is_secure: proxy_scheme == "https",
+            rewrite_bodies,
+        }
+    }
+
+    pub fn rewrites_bodies(&self) -> bool {
+        self.rewrite_bodies
+    }
+
+    /// Maps the `Origin` header sent by the browser back onto the device,
+    /// so that the device CSRF checks pass.
+    pub fn request_origin(&self, value: &str) -> Option<&str> {
+        value
+            .eq_ignore_ascii_case(&self.proxy_origin)
+            .then_some(self.device_origin.as_str())
+    }
+
+    /// Rewrites a header value holding a URL, such as `Location`.
+    pub fn url(&self, value: &str) -> String {
+        for (pattern, replacement) in &self.replacements {
+            let Some(prefix) = value.get(..pattern.len()) else {
+                continue;
+            };
+
+            let rest = &value[pattern.len()..];
+
+            if prefix.eq_ignore_ascii_case(pattern) && rest.bytes().next().is_none_or(is_host_end) {
+                return format!("{replacement}{rest}");
+            }
+        }
+
+        value.to_owned()
+    }
+
+    /// Rewrites a `Refresh` header value, e.g. `5; url=https://192.168.1.1/`.
+    pub fn refresh(&self, value: &str) -> String {
+        let Some(index) = value.to_ascii_lowercase().find("url=") else {
+            return value.to_owned();
+        };
+
+        let (head, url) = value.split_at(index + "url=".len());
+
+        format!("{head}{}", self.url(url))
+    }
+
+    /// Rewrites a `Set-Cookie` header value.
+    ///
+    /// The `Domain` attribute is dropped, which scopes the cookie to the proxy host.
+    /// `Secure` is dropped too when the proxy is reached over plain HTTP, as the
+    /// browser would otherwise discard the cookie.
+    pub fn set_cookie(&self, value: &str) -> String {
+        value
+            .split(';')
+            .enumerate()
+            .filter(|(index, attribute)| {
+                if *index == 0 {
+                    return true;
+                }
+
+                let name = attribute
+                    .split('=')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_ascii_lowercase();
+
+                match name.as_str() {
+                    "domain" => false,
+                    "secure" => self.proxy_is_secure,
+                    _ => true,
+                }
+            })
+            .map(|(_, attribute)| attribute)
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    /// Returns `true` if the body of a response with the given headers should be rewritten.
+    pub fn should_rewrite_body(&self, headers: &HeaderMap) -> bool {
+        if !self.rewrite_bodies {
+            return false;
+        }
+
+        let is_encoded = headers
+            .get(CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| !value.eq_ignore_ascii_case("identity"));
+
+        let is_rewritten_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| {
+                let value = value.to_ascii_lowercase();
+                REWRITTEN_CONTENT_TYPES
+                    .iter()
+                    .any(|content_type| value.starts_with(content_type))
+            });
+
+        !is_encoded && is_rewritten_type
+    }
+
+    pub fn body(&self) -> BodyRewriter {
+        let replacements: Vec<_> = self
+            .replacements
+            .iter()
+            .map(|(pattern, replacement)| {
+                (pattern.as_bytes().to_vec(), replacement.as_bytes().to_vec())
+            })
+            .collect();
+
+        let max_pattern_len = replacements
+            .iter()
+            .map(|(pattern, _)| pattern.len())
+            .max()
+            .unwrap_or(1);
+
+        BodyRewriter {
+            replacements,
+            max_pattern_len,
+            pending: Vec::new(),
+        }
+    }
+}
+
+/// Returns `true` if `byte`, following a host name, doesn't continue it.
+fn is_host_end(byte: u8) -> bool {
+    !(byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'-' | b':'))
+}
+
+/// Rewrites a body chunk by chunk.
+///
+/// The tail of each chunk that could be the beginning of a pattern is held back
+/// until the next chunk arrives, so that references split across chunks are rewritten too.
+#[derive(Debug)]
+pub(crate) struct BodyRewriter {
+    replacements: Vec<(Vec<u8>, Vec<u8>)>,
+    max_pattern_len: usize,
+    pending: Vec<u8>,
+}
+
+impl BodyRewriter {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(chunk);
+        self.rewrite(false)
+    }
+
+    /// Returns what is left once the body has been fully received.
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.rewrite(true)
+    }
+
+    /// Rewrites a body as it is streamed.
+    pub fn rewrite_stream(
+        self,
+        body: impl Stream<Item = io::Result<Bytes>> + 'static,
+    ) -> impl Stream<Item = io::Result<Bytes>> + 'static {
+        let state = (Box::pin(body), self, false);
+
+        futures_util::stream::unfold(state, |(mut body, mut rewriter, done)| async move {
+            if done {
+                return None;
+            }
+
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    let chunk = Bytes::from(rewriter.push(&chunk));
+                    Some((Ok(chunk), (body, rewriter, false)))
+                }
+                Some(Err(err)) => Some((Err(err), (body, rewriter, true))),
+                None => {
+                    let chunk = Bytes::from(rewriter.finish());
+                    Some((Ok(chunk), (body, rewriter, true)))
+                }
+            }
+        })
+    }
+
+    fn rewrite(&mut self, is_last: bool) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.pending.len());
+        let mut index = 0;
+
+        while index < self.pending.len() {
+            let rest = &self.pending[index..];
+
+            // Patterns must be followed by a byte that ends the host name,
+            // so the byte after the longest one is needed as well.
+            if !is_last && rest.len() <= self.max_pattern_len {
+                break;
+            }
+
+            let matched = self.replacements.iter().find(|(pattern, _)| {
+                rest.starts_with(pattern)
+                    && rest
+                        .get(pattern.len())
+                        .is_none_or(|byte| is_host_end(*byte))
+            });
+
+            if let Some((pattern, replacement)) = matched {
+                output.extend_from_slice(replacement);
+                index += pattern.len();
+                continue;
+            }
+
+            output.push(rest[0]);
+            index += 1;
+        }
+
+        self.pending.drain(..index);
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewriter() -> Rewriter {
+        let settings = DeviceUiSettings {
+            protocol: UiProtocol::Https,
+            port: 443,
+            host: "192.168.1.1".into(),
+            tls_server_name: "192.168.1.1".into(),
+            certificate_fingerprint: None,
+        };
+
+        Rewriter::new("https", "abc123.proxy.example", &settings, true)
+    }
+
+    #[test]
+    fn rewrites_location() {
+        let rewriter = rewriter();
+
+        assert_eq!(
+            rewriter.url("https://192.168.1.1/login?next=/"),
+            "https://abc123.proxy.example/login?next=/"
+        );
+        assert_eq!(
+            rewriter.url("http://192.168.1.1:443/"),
+            "https://abc123.proxy.example/"
+        );
+        assert_eq!(rewriter.url("/relative"), "/relative");
+        assert_eq!(
+            rewriter.url("https://192.168.1.10/"),
+            "https://192.168.1.10/"
+        );
+    }
+
+    #[test]
+    fn rewrites_refresh() {
+        assert_eq!(
+            rewriter().refresh("0; URL=https://192.168.1.1/index.php"),
+            "0; URL=https://abc123.proxy.example/index.php"
+        );
+    }
+
+    #[test]
+    fn rewrites_cookies() {
+        let rewriter = rewriter();
+
+        assert_eq!(
+            rewriter.set_cookie("PHPSESSID=abc; Domain=192.168.1.1; Path=/; Secure; HttpOnly"),
+            "PHPSESSID=abc; Path=/; Secure; HttpOnly"
+        );
+
+        let settings = DeviceUiSettings {
+            protocol: UiProtocol::Https,
+            port: 443,
+            host: "fw.local".into(),
+            tls_server_name: "fw.local".into(),
+            certificate_fingerprint: None,
+        };
+
+        let plain = Rewriter::new("http", "abc123.proxy.example", &settings, false);
+
+        assert_eq!(plain.set_cookie("id=1; Secure; Path=/"), "id=1; Path=/");
+    }
+
+    #[test]
+    fn rewrites_body_across_chunks() {
+        let mut body = rewriter().body();
+
+        let mut output = body.push(b"<a href=\"https://192.1");
+        output.extend(body.push(b"68.1.1/status\">status</a> <img src=\"//192.168.1.1/x.png\">"));
+        output.extend(body.push(b" <a href=\"http://192.168.1.10/\">other</a>"));
+        output.extend(body.finish());
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<a href=\"https://abc123.proxy.example/status\">status</a> \
+             <img src=\"//abc123.proxy.example/x.png\"> \
+             <a href=\"http://192.168.1.10/\">other</a>"
+        );
+    }
+
+    #[test]
+    fn maps_origin_to_device() {
+        let rewriter = rewriter();
+
+        assert_eq!(
+            rewriter.request_origin("https://abc123.proxy.example"),
+            Some("https://192.168.1.1")
+        );
+        assert_eq!(rewriter.request_origin("https://elsewhere.example"), None);
+    }
+}
@@ -0,0 +1,77 @@
+use super::request::RequestBody;
+use hyper::body::Incoming;
+use hyper::client::conn::{http1, http2};
+use hyper::{Request, Response, Uri, Version};
+
+/// Sending half of an established connection to a device.
+#[derive(Debug)]
+pub(crate) enum RequestSender {
+    Http1(http1::SendRequest<RequestBody>),
+    /// HTTP/2 connections carry concurrent requests, so the sender can be shared.
+    Http2(http2::SendRequest<RequestBody>),
+}
+
+impl RequestSender {
+    /// Waits until the connection can send a new request.
+    pub async fn ready(&mut self) -> hyper::Result<()> {
+        match self {
+            RequestSender::Http1(sender) => sender.ready().await,
+            RequestSender::Http2(sender) => sender.ready().await,
+        }
+    }
+
+    pub async fn send_request(
+        &mut self,
+        request: Request<RequestBody>,
+    ) -> hyper::Result<Response<Incoming>> {
+        match self {
+            RequestSender::Http1(sender) => sender.send_request(request).await,
+            RequestSender::Http2(sender) => sender.send_request(into_http2(request)).await,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        match self {
+            RequestSender::Http1(sender) => sender.is_closed(),
+            RequestSender::Http2(sender) => sender.is_closed(),
+        }
+    }
+
+    /// Returns `true` if the connection carries concurrent requests.
+    pub fn is_multiplexed(&self) -> bool {
+        matches!(self, RequestSender::Http2(_))
+    }
+
+    /// Returns another handle to the connection, if it can be shared.
+    pub fn try_clone(&self) -> Option<Self> {
+        match self {
+            RequestSender::Http1(_) => None,
+            RequestSender::Http2(sender) => Some(RequestSender::Http2(sender.clone())),
+        }
+    }
+}
+
+/// Moves the `Host` header into the request URI, where HTTP/2 expects the authority.
+fn into_http2(mut request: Request<RequestBody>) -> Request<RequestBody> {
+    *request.version_mut() = Version::HTTP_2;
+
+    let Some(host) = request.headers_mut().remove(hyper::header::HOST) else {
+        return request;
+    };
+
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+
+    let uri = host
+        .to_str()
+        .ok()
+        .and_then(|host| Uri::try_from(format!("https://{host}{path_and_query}")).ok());
+
+    if let Some(uri) = uri {
+        *request.uri_mut() = uri;
+    }
+
+    request
+}
@@ -0,0 +1,151 @@
+//! TLS towards device web UIs.
+//!
+//! Firewall GUIs almost always serve a self-signed certificate, so the certificate chain is
+//! not validated. Instead, the certificate is trusted on first use: its fingerprint is pinned
+//! on the first successful connection, and any later connection presenting a different
+//! certificate is rejected.
+
+use crate::reverse_tunnel::TunnelStream;
+use crate::utilities::certificates;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, ClientConfig, DigitallySignedStruct, SignatureScheme};
+use std::sync::{Arc, Mutex};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+#[derive(Debug)]
+pub(crate) enum HandshakeError {
+    /// The device presented a certificate other than the pinned one.
+    CertificateMismatch {
+        expected: String,
+        presented: String,
+    },
+    Failed(Error),
+}
+
+/// Performs a TLS handshake with a device web UI over a tunneled stream.
+///
+/// If `pinned` is set, the device must present the certificate with this fingerprint.
+/// `alpn` lists the application protocols offered to the device, by order of preference.
+/// Returns the established stream along with the fingerprint of the presented certificate.
+pub(crate) async fn handshake(
+    stream: TunnelStream,
+    server_name: &str,
+    pinned: Option<&str>,
+    alpn: &[&[u8]],
+) -> Result<(TlsStream<TunnelStream>, String), HandshakeError> {
+    let verifier = Arc::new(PinningVerifier {
+        provider: Arc::new(rustls::crypto::aws_lc_rs::default_provider()),
+        pinned: pinned.map(str::to_owned),
+        presented: Mutex::default(),
+    });
+
+    let mut config = ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+
+    config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+
+    let connector = TlsConnector::from(Arc::new(config));
+
+    let server_name = ServerName::try_from(server_name.to_owned())
+        .handle_err(location!())
+        .map_err(HandshakeError::Failed)?;
+
+    let result = connector.connect(server_name, stream).await;
+
+    let presented = verifier.presented.lock().unwrap().take();
+
+    match (result, presented, pinned) {
+        (Ok(stream), Some(presented), _) => Ok((stream, presented)),
+        (Err(_), Some(presented), Some(pinned)) if !pinned.eq_ignore_ascii_case(&presented) => {
+            Err(HandshakeError::CertificateMismatch {
+                expected: pinned.to_owned(),
+                presented,
+            })
+        }
+        (Ok(_), None, _) => Err("No certificate presented by the device")
+            .handle_err(location!())
+            .map_err(HandshakeError::Failed),
+        (Err(err), ..) => Err(err)
+            .handle_err(location!())
+            .map_err(HandshakeError::Failed),
+    }
+}
+
+/// Accepts the pinned certificate only, or any certificate if none is pinned yet.
+///
+/// Handshake signatures are still verified, so the device has to own the key of
+/// the certificate it presents.
+#[derive(Debug)]
+struct PinningVerifier {
+    provider: Arc<CryptoProvider>,
+    pinned: Option<String>,
+    presented: Mutex<Option<String>>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let presented = certificates::fingerprint(end_entity);
+
+        let matches = self
+            .pinned
+            .as_ref()
+            .is_none_or(|pinned| pinned.eq_ignore_ascii_case(&presented));
+
+        *self.presented.lock().unwrap() = Some(presented);
+
+        if matches {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
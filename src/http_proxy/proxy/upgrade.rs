@@ -0,0 +1,124 @@
+//! Protocol upgrades, e.g. WebSockets, through the UI proxy.
+//!
+//! The upgrade request is forwarded to the device like any other request. If the device
+//! switches protocols, the connection stops carrying HTTP and raw bytes are relayed in both
+//! directions until either side closes it or the tunnel is closed.
+
+use super::request::{self, ResponseStream};
+use super::rewrite::Rewriter;
+use super::sender::RequestSender;
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use crate::orchestrator::TunnelGuard;
+use actix_web::HttpRequest as ActixRequest;
+use actix_web::HttpResponse as ActixResponse;
+use actix_web::web::Payload as ActixBody;
+use futures_util::StreamExt;
+use hyper::StatusCode;
+use hyper::body::Bytes;
+use hyper_util::rt::TokioIo;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+/// Size of the buffer used to read from an upgraded connection.
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Returns `true` if the client asks to switch protocols.
+pub fn is_upgrade(request: &ActixRequest) -> bool {
+    request.head().upgrade()
+}
+
+/// Forwards an upgrade request and, if the device accepts it, relays the upgraded connection.
+///
+/// The connection can't be reused afterwards, so `sender` and `tunnel` are consumed.
+pub async fn proxy_upgrade(
+    mut sender: RequestSender,
+    tunnel: Arc<TunnelGuard>,
+    request: ActixRequest,
+    mut body: ActixBody,
+    host: &str,
+    rewriter: &Rewriter,
+) -> ActixResponse {
+    let upgrade_request = request::convert_request(&request, request::empty_body(), host, rewriter);
+
+    let Ok(upgrade_request) = upgrade_request else {
+        return ActixResponse::InternalServerError().into();
+    };
+
+    let mut response = match request::send_request(&mut sender, upgrade_request).await {
+        Ok(response) => response,
+        Err(resp) => return resp,
+    };
+
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        let tunnel_closed = tunnel.closed();
+        return request::stream_response(response, rewriter, tunnel_closed, move || drop(tunnel));
+    }
+
+    let protocol = response
+        .headers()
+        .get(hyper::header::UPGRADE)
+        .map(|value| value.as_bytes().to_vec())
+        .unwrap_or_default();
+
+    let mut response_builder = ActixResponse::SwitchingProtocols();
+    request::copy_response_headers(&mut response_builder, response.headers(), rewriter);
+
+    let upgraded = match hyper::upgrade::on(&mut response).await {
+        Ok(upgraded) => TokioIo::new(upgraded),
+        Err(err) => {
+            log::error!("Device UI failed to switch protocols: {err}");
+            return ActixResponse::BadGateway()
+                .json(ErrorJson::from("Device failed to switch protocols"));
+        }
+    };
+
+    let (reader, mut writer) = tokio::io::split(upgraded);
+
+    // Client to device. The payload of an upgrade request carries every byte the
+    // client sends after the request head.
+    let tunnel_closed = tunnel.closed();
+    actix_web::rt::spawn(async move {
+        let relay = async {
+            while let Some(Ok(chunk)) = body.next().await {
+                writer.write_all(&chunk).await?;
+            }
+
+            writer.shutdown().await
+        };
+
+        tokio::select! {
+            result = relay => {
+                if let Err(err) = result {
+                    log::debug!("Upgraded connection to the device closed: {err}");
+                }
+            }
+            _ = tunnel_closed => {}
+        }
+    });
+
+    // Device to client.
+    let tunnel_closed = tunnel.closed();
+    let stream = ResponseStream::new(read_chunks(reader), tunnel_closed, move || drop(tunnel));
+
+    response_builder
+        .upgrade(protocol.as_slice())
+        .streaming(stream)
+}
+
+fn read_chunks(
+    reader: impl AsyncRead + Unpin + 'static,
+) -> impl futures_util::Stream<Item = io::Result<Bytes>> {
+    futures_util::stream::try_unfold(reader, |mut reader| async move {
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
+        let read = reader.read(&mut buffer).await?;
+
+        if read == 0 {
+            return Ok(None);
+        }
+
+        buffer.truncate(read);
+
+        Ok(Some((Bytes::from(buffer), reader)))
+    })
+}
@@ -1,14 +1,18 @@
 use super::utilities::error_json::ErrorJson;
 use super::utilities::request_handling;
+use super::utilities::terminal::TerminalSize;
 use super::utilities::tunneling;
 use crate::app_context::AppContext;
-use crate::datastore::RemoteAccessType;
+use crate::command_audit::CommandAuditor;
+use crate::datastore::{RemoteAccessType, SSHKeypair};
+use crate::http_proxy::config::HttpProxyConfig;
 use actix_web::HttpRequest;
 use actix_web::HttpResponse;
 use actix_web::Responder;
 use actix_web::rt;
 use actix_web::web::{Data, Payload};
 use relay::relay;
+use ssh_session::{SSHSession, SSHSessionError};
 
 mod relay;
 mod ssh_session;
@@ -16,6 +20,7 @@ mod ssh_session;
 pub(super) async fn open_ssh_session(
     request: HttpRequest,
     context: Data<AppContext>,
+    config: Data<HttpProxyConfig>,
     body: Payload,
 ) -> impl Responder {
     let session_token = match request_handling::extract_session_token(&request) {
@@ -57,33 +62,114 @@ pub(super) async fn open_ssh_session(
         return HttpResponse::NotFound().json(ErrorJson::from("Device is unauthorized"));
     }
 
-    let keypair =
-        match request_handling::fetch_ssh_keypair(&context, &token.jwt, &session.device_id).await {
-            Ok(kp) => kp,
-            Err(resp) => return resp,
-        };
+    let keypair = match request_handling::fetch_ssh_keypair(
+        &context,
+        &token.jwt,
+        &session.device_id,
+        config.ssh_key_ttl,
+    )
+    .await
+    {
+        Ok(kp) => kp,
+        Err(resp) => return resp,
+    };
 
-    let Ok(stream) =
-        tunneling::establish_tunneled_ssh(&context, &device.uuid, &keypair.public_key).await
+    let Ok((stream, tunnel)) = tunneling::establish_tunneled_ssh(
+        &context,
+        &device.uuid,
+        &keypair.public_key,
+        device.ssh_login_user(),
+    )
+    .await
     else {
         return HttpResponse::InternalServerError()
             .json(ErrorJson::from("Failed to establish a tunnel"));
     };
 
-    let ssh_session = match ssh_session::SSHSession::new(stream, &keypair).await {
-        Ok(sess) => sess,
-        Err(_) => {
+    if let Err(resp) = request_handling::consume_session(&context, &token.jwt, &session).await {
+        return resp;
+    }
+
+    let size = TerminalSize::from_query(&request);
+
+    let ssh_session = match SSHSession::new(stream, &keypair, device.ssh_login_user(), size).await {
+        Ok((sess, fingerprint)) => {
+            if keypair.host_key_fingerprint.is_none() {
+                pin_host_key(&context, &keypair, &fingerprint).await;
+            }
+            sess
+        }
+        Err(SSHSessionError::HostKeyMismatch {
+            expected,
+            presented,
+        }) => {
+            log::warn!(
+                "Device {} presented SSH host key {presented}, expected pinned host key {expected}",
+                device.id
+            );
+
+            return HttpResponse::BadGateway().json(ErrorJson::from(
+                "The device presented an SSH host key different from the pinned one",
+            ));
+        }
+        Err(SSHSessionError::Failed(_)) => {
             return HttpResponse::InternalServerError()
                 .json(ErrorJson::from("Failed to establish SSH session"));
         }
     };
 
-    let (response, ws_session, stream) = match request_handling::upgrade_to_websocket(request, body)
-    {
-        Ok(r) => r,
+    let recording = match request_handling::start_recording(&context, &session, size).await {
+        Ok(recording) => recording,
         Err(resp) => return resp,
     };
 
-    rt::spawn(relay(stream, ws_session, ssh_session));
+    let (mut response, ws_session, stream) =
+        match request_handling::upgrade_to_websocket(request, body) {
+            Ok(r) => r,
+            Err(resp) => {
+                if let Some(recording) = &recording {
+                    recording.finish().await;
+                }
+                return resp;
+            }
+        };
+
+    request_handling::set_recording_header(&mut response, recording.as_ref());
+
+    let recording_id = recording
+        .as_ref()
+        .map(|recording| recording.id().to_owned());
+    let auditor = CommandAuditor::new(context.clone(), &session, recording_id);
+
+    rt::spawn(relay(
+        stream,
+        ws_session,
+        ssh_session,
+        tunnel,
+        recording,
+        auditor,
+    ));
     response
 }
+
+/// Records the host key presented by a device on first use.
+async fn pin_host_key(context: &AppContext, keypair: &SSHKeypair, fingerprint: &str) {
+    let device_id = &keypair.device_id;
+
+    let Ok(token) = context.sysdev_token_provider.get().await else {
+        log::error!("Failed to pin SSH host key of device {device_id}: no sysdev token");
+        return;
+    };
+
+    match context
+        .datastore
+        .update_ssh_host_key_fingerprint(&token.jwt, &keypair.id, Some(fingerprint))
+        .await
+    {
+        Ok(_) => log::info!("Pinned SSH host key {fingerprint} of device {device_id}"),
+        Err(err) => log::error!(
+            "Failed to pin SSH host key of device {device_id}: {}",
+            err.to_str()
+        ),
+    }
+}
@@ -1,9 +1,11 @@
 use super::ssh_session::SSHSession;
+use crate::command_audit::CommandAuditor;
+use crate::http_proxy::utilities::terminal::ControlMessage;
+use crate::orchestrator::TunnelGuard;
+use crate::session_recording::SessionRecording;
 use actix_ws::{AggregatedMessage, AggregatedMessageStream, MessageStream, Session as WSSession};
 use futures_util::StreamExt as _;
-use prost::bytes::Bytes;
-use tokio::io::AsyncReadExt;
-use tokio::io::AsyncWriteExt;
+use nullnet_liberror::Error;
 
 /// Starts bi-directional message relaying between a WebSocket session and an SSH session.
 ///
@@ -15,7 +17,17 @@ use tokio::io::AsyncWriteExt;
 /// - `stream`: The WebSocket message stream.
 /// - `ws_session`: The WebSocket session used to send messages back to the client.
 /// - `ssh_session`: The SSH session used to read and write data.
-pub(crate) async fn relay(stream: MessageStream, ws_session: WSSession, ssh_session: SSHSession) {
+/// - `tunnel`: Guard of the underlying tunnel; the relay stops when the tunnel is closed.
+/// - `recording`: Recording of the session, if it is recorded.
+/// - `auditor`: Audit trail the commands entered in the session are added to.
+pub(crate) async fn relay(
+    stream: MessageStream,
+    ws_session: WSSession,
+    ssh_session: SSHSession,
+    tunnel: TunnelGuard,
+    recording: Option<SessionRecording>,
+    auditor: CommandAuditor,
+) {
     let stream = stream
         .aggregate_continuations()
         .max_continuation_size(2_usize.pow(20));
@@ -24,54 +36,89 @@ pub(crate) async fn relay(stream: MessageStream, ws_session: WSSession, ssh_sess
         _ = relay_messages_from_user_to_client(
             stream,
             ssh_session.clone(),
-            ws_session.clone()
+            ws_session.clone(),
+            recording.clone(),
+            auditor.clone(),
         ) => {
             log::info!("WebSocket → SSH relay ended.");
         }
-        _ = relay_messages_from_ssh_to_client(ws_session, ssh_session) => {
+        _ = relay_messages_from_ssh_to_client(ws_session, ssh_session, recording.clone(), auditor) => {
             log::info!("SSH → WebSocket relay ended.");
         }
+        _ = tunnel.closed() => {
+            log::info!("Tunnel closed by the server, ending the SSH relay.");
+        }
+    }
+
+    if let Some(recording) = recording {
+        recording.finish().await;
     }
 }
 
-/// Relays incoming WebSocket messages to the SSH session's input stream.
+/// Relays incoming WebSocket messages to the SSH session.
 ///
 /// Handles text, binary, and ping messages:
-/// - Text/Binary messages are forwarded to the SSH session.
+/// - Text messages holding a control message (see `utilities::terminal`) are applied
+///   to the SSH session, other text messages are forwarded as input.
+/// - Binary messages are forwarded to the SSH session.
 /// - Ping messages are responded to with Pong.
 ///
 /// # Parameters
 /// - `stream`: Aggregated WebSocket message stream.
 /// - `ssh_session`: SSH session for writing received data.
 /// - `ws_session`: WebSocket session used to respond to Ping messages.
+/// - `recording`: Recording the user input is recorded into, if any.
+/// - `auditor`: Audit trail the entered commands are added to.
 async fn relay_messages_from_user_to_client(
     mut stream: AggregatedMessageStream,
     ssh_session: SSHSession,
     mut ws_session: WSSession,
+    recording: Option<SessionRecording>,
+    auditor: CommandAuditor,
 ) {
     while let Some(msg) = stream.next().await {
         match msg {
             Ok(AggregatedMessage::Text(text)) => {
-                if let Err(err) = ssh_session
-                    .writer
-                    .lock()
-                    .await
-                    .write_all(text.as_bytes())
-                    .await
-                {
-                    log::error!("WS → SSH: Failed to write text: {}", err);
+                let len = text.len();
+
+                let result = match ControlMessage::parse(&text) {
+                    Some(message) => {
+                        apply_control_message(&ssh_session, message, recording.as_ref(), &auditor)
+                            .await
+                    }
+                    None => {
+                        if let Some(recording) = &recording {
+                            recording.input(text.as_bytes()).await;
+                        }
+
+                        auditor.input(text.as_bytes());
+
+                        ssh_session.write(text.into_bytes()).await
+                    }
+                };
+
+                if let Err(err) = result {
+                    log::error!("WS → SSH: Failed to write text: {}", err.to_str());
                     return;
                 } else {
-                    log::debug!("WS → SSH: Sent text ({} bytes)", text.len());
+                    log::debug!("WS → SSH: Sent text ({} bytes)", len);
                 }
             }
 
             Ok(AggregatedMessage::Binary(bin)) => {
-                if let Err(err) = ssh_session.writer.lock().await.write_all(&bin).await {
-                    log::error!("WS → SSH: Failed to write binary: {}", err);
+                let len = bin.len();
+
+                if let Some(recording) = &recording {
+                    recording.input(&bin).await;
+                }
+
+                auditor.input(&bin);
+
+                if let Err(err) = ssh_session.write(bin).await {
+                    log::error!("WS → SSH: Failed to write binary: {}", err.to_str());
                     return;
                 } else {
-                    log::debug!("WS → SSH: Sent binary ({} bytes)", bin.len());
+                    log::debug!("WS → SSH: Sent binary ({} bytes)", len);
                 }
             }
 
@@ -98,6 +145,41 @@ async fn relay_messages_from_user_to_client(
     log::info!("WS → SSH: WebSocket stream closed.");
 }
 
+async fn apply_control_message(
+    ssh_session: &SSHSession,
+    message: ControlMessage,
+    recording: Option<&SessionRecording>,
+    auditor: &CommandAuditor,
+) -> Result<(), Error> {
+    match message {
+        ControlMessage::Resize(size) => {
+            log::debug!("WS → SSH: Resizing terminal to {}x{}", size.cols, size.rows);
+
+            if let Some(recording) = recording {
+                recording.resize(size).await;
+            }
+
+            ssh_session.resize(size).await
+        }
+        ControlMessage::Signal(signal) => {
+            let input = [signal.control_character()];
+
+            if let Some(recording) = recording {
+                recording.input(&input).await;
+            }
+
+            auditor.input(&input);
+
+            ssh_session.write(input.to_vec()).await
+        }
+        ControlMessage::Keepalive => Ok(()),
+        ControlMessage::Unsupported(reason) => {
+            log::warn!("WS → SSH: Ignored control message: {}", reason);
+            Ok(())
+        }
+    }
+}
+
 /// Relays data read from the SSH session to the WebSocket session.
 ///
 /// Reads raw bytes from the SSH session and sends them as binary messages
@@ -106,31 +188,32 @@ async fn relay_messages_from_user_to_client(
 /// # Parameters
 /// - `ws_session`: The WebSocket session to send binary data.
 /// - `ssh_session`: The SSH session to read from.
-async fn relay_messages_from_ssh_to_client(mut ws_session: WSSession, ssh_session: SSHSession) {
-    loop {
-        let mut buf = [0u8; 8196];
-        match ssh_session.reader.lock().await.read(&mut buf).await {
-            Ok(0) => {
-                log::info!("SSH → WS: Reached EOF (client disconnected).");
-                break;
-            }
-            Ok(n) => {
-                let binmsg = Bytes::copy_from_slice(&buf[..n]);
-                if let Err(err) = ws_session.binary(binmsg).await {
-                    log::error!(
-                        "SSH → WS: Failed to send binary message ({} bytes): {}",
-                        n,
-                        err
-                    );
-                    break;
-                } else {
-                    log::debug!("SSH → WS: Sent binary ({} bytes)", n);
-                }
-            }
-            Err(err) => {
-                log::error!("SSH → WS: Failed to read from SSH session: {}", err);
-                break;
-            }
+/// - `recording`: Recording the output is recorded into, if any.
+/// - `auditor`: Audit trail following the output, e.g. to recognize password prompts.
+async fn relay_messages_from_ssh_to_client(
+    mut ws_session: WSSession,
+    ssh_session: SSHSession,
+    recording: Option<SessionRecording>,
+    auditor: CommandAuditor,
+) {
+    while let Some(binmsg) = ssh_session.read().await {
+        let n = binmsg.len();
+
+        if let Some(recording) = &recording {
+            recording.output(&binmsg).await;
+        }
+
+        auditor.output(&binmsg);
+
+        if let Err(err) = ws_session.binary(binmsg).await {
+            log::error!(
+                "SSH → WS: Failed to send binary message ({} bytes): {}",
+                n,
+                err
+            );
+            break;
+        } else {
+            log::debug!("SSH → WS: Sent binary ({} bytes)", n);
         }
     }
 
@@ -1,57 +1,253 @@
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 
 use crate::datastore::SSHKeypair;
-use async_ssh2_lite::{AsyncChannel, AsyncSession};
+use crate::http_proxy::utilities::terminal::TerminalSize;
+use crate::reverse_tunnel::TunnelStream;
+use crate::utilities::ssh::host_key_fingerprint;
+use async_ssh2_lite::{AsyncChannel, AsyncSession, AsyncSessionStream};
 use nullnet_liberror::{Error, ErrorHandler, Location, location};
-use tokio::io::{ReadHalf, WriteHalf};
-use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use prost::bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::sync::{Mutex, mpsc};
 
-type Reader = ReadHalf<AsyncChannel<TcpStream>>;
-type Writer = WriteHalf<AsyncChannel<TcpStream>>;
+/// Size of the buffer used to read from the shell.
+const READ_BUFFER_SIZE: usize = 8196;
 
+/// Number of pending inputs or outputs after which the shell applies backpressure.
+const CHANNEL_CAPACITY: usize = 64;
+
+trait ShellChannel: AsyncRead + AsyncWrite + Send + Unpin {
+    fn resize(
+        &mut self,
+        size: TerminalSize,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>>;
+}
+
+impl<S> ShellChannel for AsyncChannel<S>
+where
+    S: AsyncSessionStream + Send + Sync + 'static,
+{
+    fn resize(
+        &mut self,
+        size: TerminalSize,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + '_>> {
+        Box::pin(async move {
+            self.request_pty_size(size.cols.into(), size.rows.into(), None, None)
+                .await
+                .handle_err(location!())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum SSHSessionError {
+    /// The device presented a host key other than the pinned one.
+    HostKeyMismatch {
+        expected: String,
+        presented: String,
+    },
+    Failed(Error),
+}
+
+impl From<Error> for SSHSessionError {
+    fn from(err: Error) -> Self {
+        SSHSessionError::Failed(err)
+    }
+}
+
+enum ShellInput {
+    Data(Bytes),
+    Resize(TerminalSize),
+}
+
+/// Interactive shell opened over SSH.
+///
+/// The channel is owned by a dedicated task, as reading, writing and resizing
+/// all need exclusive access to it. The task ends, closing the channel, once
+/// every handle to the session has been dropped or the shell exits.
 #[derive(Clone)]
 pub(crate) struct SSHSession {
-    pub(crate) reader: Arc<Mutex<Reader>>,
-    pub(crate) writer: Arc<Mutex<Writer>>,
+    input: mpsc::Sender<ShellInput>,
+    output: Arc<Mutex<mpsc::Receiver<Bytes>>>,
 }
 
 impl SSHSession {
-    pub async fn new(stream: TcpStream, key: &SSHKeypair) -> Result<Self, Error> {
-        let mut session = AsyncSession::new(stream, None).handle_err(location!())?;
-
-        session.handshake().await.handle_err(location!())?;
-
-        session
-            .userauth_pubkey_memory(
-                "root",
-                Some(&key.public_key),
-                &key.private_key,
-                Some(&key.passphrase),
-            )
-            .await
-            .handle_err(location!())?;
+    /// Logs in as `user` and opens a shell with a terminal of the given size,
+    /// or of the server default size.
+    ///
+    /// If `key` has a pinned host key, the device must present it.
+    /// Returns the session along with the fingerprint of the presented host key.
+    pub async fn new(
+        stream: TunnelStream,
+        key: &SSHKeypair,
+        user: &str,
+        size: Option<TerminalSize>,
+    ) -> Result<(Self, String), SSHSessionError> {
+        let (channel, fingerprint): (Box<dyn ShellChannel>, String) = match stream {
+            TunnelStream::Plain(stream) => {
+                let (channel, fingerprint) = open_shell(stream, key, user, size).await?;
+                (Box::new(channel), fingerprint)
+            }
+            mut stream => {
+                // libssh2 operates on a raw socket, so the decrypted or demultiplexed
+                // bytes are relayed through a local socket pair.
+                let (local, mut remote) = UnixStream::pair().handle_err(location!())?;
+
+                tokio::spawn(async move {
+                    if let Err(err) = tokio::io::copy_bidirectional(&mut stream, &mut remote).await
+                    {
+                        log::debug!("SSH tunnel bridge closed: {}", err);
+                    }
+                });
 
-        session
-            .authenticated()
-            .then_some(())
-            .ok_or("SSH Session authentication failed")
-            .handle_err(location!())?;
+                let (channel, fingerprint) = open_shell(local, key, user, size).await?;
+                (Box::new(channel), fingerprint)
+            }
+        };
 
-        let mut channel = session.channel_session().await.handle_err(location!())?;
+        let (input, input_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let (output_tx, output) = mpsc::channel(CHANNEL_CAPACITY);
 
-        channel
-            .request_pty("xterm", None, None)
+        tokio::spawn(drive_shell(channel, input_rx, output_tx));
+
+        let session = Self {
+            input,
+            output: Arc::new(Mutex::new(output)),
+        };
+
+        Ok((session, fingerprint))
+    }
+
+    /// Writes terminal input to the shell.
+    pub async fn write(&self, data: impl Into<Bytes>) -> Result<(), Error> {
+        self.input
+            .send(ShellInput::Data(data.into()))
             .await
-            .handle_err(location!())?;
+            .handle_err(location!())
+    }
 
-        channel.shell().await.handle_err(location!())?;
+    /// Resizes the terminal of the shell.
+    pub async fn resize(&self, size: TerminalSize) -> Result<(), Error> {
+        self.input
+            .send(ShellInput::Resize(size))
+            .await
+            .handle_err(location!())
+    }
 
-        let (reader, writer) = tokio::io::split(channel);
+    /// Reads the next output of the shell, or `None` once the shell has exited.
+    pub async fn read(&self) -> Option<Bytes> {
+        self.output.lock().await.recv().await
+    }
+}
 
-        Ok(Self {
-            reader: Arc::new(Mutex::new(reader)),
-            writer: Arc::new(Mutex::new(writer)),
-        })
+/// Relays inputs to the shell and its output back, until either side is closed.
+async fn drive_shell(
+    mut channel: Box<dyn ShellChannel>,
+    mut input: mpsc::Receiver<ShellInput>,
+    output: mpsc::Sender<Bytes>,
+) {
+    let mut buf = [0u8; READ_BUFFER_SIZE];
+
+    loop {
+        tokio::select! {
+            read = channel.read(&mut buf) => match read {
+                Ok(0) => {
+                    log::info!("SSH shell reached EOF.");
+                    return;
+                }
+                Ok(n) => {
+                    if output.send(Bytes::copy_from_slice(&buf[..n])).await.is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    log::error!("Failed to read from SSH shell: {}", err);
+                    return;
+                }
+            },
+            message = input.recv() => {
+                let result = match message {
+                    Some(ShellInput::Data(data)) => {
+                        channel.write_all(&data).await.handle_err(location!())
+                    }
+                    Some(ShellInput::Resize(size)) => channel.resize(size).await,
+                    None => return,
+                };
+
+                if let Err(err) = result {
+                    log::error!("Failed to write to SSH shell: {}", err.to_str());
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Verifies the host key, authenticates over the given stream and opens an interactive shell.
+///
+/// The host key is checked before authenticating, so that nothing is sent to a spoofed endpoint.
+async fn open_shell<S>(
+    stream: S,
+    key: &SSHKeypair,
+    user: &str,
+    size: Option<TerminalSize>,
+) -> Result<(AsyncChannel<S>, String), SSHSessionError>
+where
+    S: AsyncSessionStream + Send + Sync + 'static,
+{
+    let mut session = AsyncSession::new(stream, None).handle_err(location!())?;
+
+    session.handshake().await.handle_err(location!())?;
+
+    let presented = session
+        .host_key()
+        .map(|(host_key, _)| host_key_fingerprint(host_key))
+        .ok_or("No host key presented by the device")
+        .handle_err(location!())?;
+
+    let mismatch = key
+        .host_key_fingerprint
+        .as_ref()
+        .filter(|expected| **expected != presented);
+
+    if let Some(expected) = mismatch {
+        return Err(SSHSessionError::HostKeyMismatch {
+            expected: expected.clone(),
+            presented,
+        });
     }
+
+    session
+        .userauth_pubkey_memory(
+            user,
+            Some(&key.public_key),
+            &key.private_key,
+            Some(&key.passphrase),
+        )
+        .await
+        .handle_err(location!())?;
+
+    session
+        .authenticated()
+        .then_some(())
+        .ok_or("SSH Session authentication failed")
+        .handle_err(location!())?;
+
+    let mut channel = session.channel_session().await.handle_err(location!())?;
+
+    channel
+        .request_pty(
+            "xterm",
+            None,
+            size.map(|size| (size.cols.into(), size.rows.into(), 0, 0)),
+        )
+        .await
+        .handle_err(location!())?;
+
+    channel.shell().await.handle_err(location!())?;
+
+    Ok((channel, presented))
 }
@@ -1,14 +1,16 @@
 use super::utilities::error_json::ErrorJson;
 use super::utilities::request_handling;
+use super::utilities::terminal::TerminalSize;
 use super::utilities::tunneling;
 use crate::app_context::AppContext;
+use crate::command_audit::CommandAuditor;
 use crate::datastore::RemoteAccessType;
 use actix_web::HttpRequest;
 use actix_web::HttpResponse;
 use actix_web::Responder;
 use actix_web::rt;
 use actix_web::web::{Data, Payload};
-use relay::relay;
+use relay::{TtyResizer, relay};
 
 mod relay;
 
@@ -56,18 +58,46 @@ pub(super) async fn open_tty_session(
         return HttpResponse::NotFound().json(ErrorJson::from("Device is unauthorized"));
     }
 
-    let Ok(stream) = tunneling::establish_tunneled_tty(&context, &device.uuid).await else {
+    let Ok((stream, tunnel, tunnel_token)) =
+        tunneling::establish_tunneled_tty(&context, &device.uuid).await
+    else {
         return HttpResponse::InternalServerError()
             .json(ErrorJson::from("Failed to establish a tunnel"));
     };
 
-    let (response, ws_session, ws_stream) =
+    if let Err(resp) = request_handling::consume_session(&context, &token.jwt, &session).await {
+        return resp;
+    }
+
+    let size = TerminalSize::from_query(&request);
+    let resizer = TtyResizer::new(context.clone(), device.uuid, tunnel_token, size);
+
+    let recording = match request_handling::start_recording(&context, &session, size).await {
+        Ok(recording) => recording,
+        Err(resp) => return resp,
+    };
+
+    let (mut response, ws_session, ws_stream) =
         match request_handling::upgrade_to_websocket(request, body) {
             Ok(r) => r,
-            Err(resp) => return resp,
+            Err(resp) => {
+                if let Some(recording) = &recording {
+                    recording.finish().await;
+                }
+                return resp;
+            }
         };
 
-    rt::spawn(relay(ws_stream, ws_session, stream));
+    request_handling::set_recording_header(&mut response, recording.as_ref());
+
+    let recording_id = recording
+        .as_ref()
+        .map(|recording| recording.id().to_owned());
+    let auditor = CommandAuditor::new(context.clone(), &session, recording_id);
+
+    rt::spawn(relay(
+        ws_stream, ws_session, stream, tunnel, resizer, recording, auditor,
+    ));
 
     response
 }
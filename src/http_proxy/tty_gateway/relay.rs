@@ -1,41 +1,155 @@
+use crate::app_context::AppContext;
+use crate::command_audit::CommandAuditor;
+use crate::http_proxy::utilities::terminal::{ControlMessage, TerminalSize};
+use crate::orchestrator::TunnelGuard;
+use crate::reverse_tunnel::TunnelStream;
+use crate::session_recording::SessionRecording;
+use actix_web::web::Data;
 use actix_ws::{AggregatedMessage, AggregatedMessageStream, MessageStream, Session as WSSession};
 use futures_util::StreamExt as _;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
 use prost::bytes::Bytes;
 use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::io::ReadHalf;
 use tokio::io::WriteHalf;
-use tokio::net::TcpStream;
 
-pub(crate) async fn relay(msg_stream: MessageStream, ws_session: WSSession, tty_stream: TcpStream) {
+/// Forwards terminal resizes to the client serving a TTY session.
+///
+/// The TTY tunnel carries terminal data only, so resizes go through the control channel,
+/// identifying the session by the token of its tunnel.
+pub(crate) struct TtyResizer {
+    context: Data<AppContext>,
+    device_uuid: String,
+    tunnel_token: String,
+    /// Size the terminal was opened with, if given.
+    initial_size: Option<TerminalSize>,
+}
+
+impl TtyResizer {
+    pub fn new(
+        context: Data<AppContext>,
+        device_uuid: String,
+        tunnel_token: String,
+        initial_size: Option<TerminalSize>,
+    ) -> Self {
+        Self {
+            context,
+            device_uuid,
+            tunnel_token,
+            initial_size,
+        }
+    }
+
+    /// Applies the size the terminal was opened with, if any.
+    pub async fn apply_initial_size(&self) -> Result<(), Error> {
+        match self.initial_size {
+            Some(size) => self.resize(size).await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn resize(&self, size: TerminalSize) -> Result<(), Error> {
+        let client = self
+            .context
+            .orchestractor
+            .get_client(&self.device_uuid)
+            .await
+            .ok_or("Device is not connected")
+            .handle_err(location!())?;
+
+        // The resize is applied asynchronously, the command result is not awaited.
+        let _ = client
+            .lock()
+            .await
+            .resize_tty(self.tunnel_token.clone(), size.cols, size.rows)
+            .await?;
+
+        Ok(())
+    }
+}
+
+pub(crate) async fn relay(
+    msg_stream: MessageStream,
+    ws_session: WSSession,
+    tty_stream: TunnelStream,
+    tunnel: TunnelGuard,
+    resizer: TtyResizer,
+    recording: Option<SessionRecording>,
+    auditor: CommandAuditor,
+) {
     let stream = msg_stream
         .aggregate_continuations()
         .max_continuation_size(2_usize.pow(20));
 
+    if let Err(err) = resizer.apply_initial_size().await {
+        log::warn!("Failed to set the initial TTY size: {}", err.to_str());
+    }
+
     let (tty_reader, tty_writer) = tokio::io::split(tty_stream);
 
     tokio::select! {
         _ = relay_messages_from_user_to_client(
             stream,
             tty_writer,
-            ws_session.clone()
+            ws_session.clone(),
+            resizer,
+            recording.clone(),
+            auditor.clone(),
         ) => {
             log::info!("WebSocket → TTY relay ended.");
         }
-        _ = relay_messages_from_ssh_to_client(ws_session, tty_reader) => {
+        _ = relay_messages_from_ssh_to_client(ws_session, tty_reader, recording.clone(), auditor) => {
             log::info!("TTY → WebSocket relay ended.");
         }
+        _ = tunnel.closed() => {
+            log::info!("Tunnel closed by the server, ending the TTY relay.");
+        }
+    }
+
+    if let Some(recording) = recording {
+        recording.finish().await;
     }
 }
 
 async fn relay_messages_from_user_to_client(
     mut stream: AggregatedMessageStream,
-    mut tty_writer: WriteHalf<TcpStream>,
+    mut tty_writer: WriteHalf<TunnelStream>,
     mut ws_session: WSSession,
+    resizer: TtyResizer,
+    recording: Option<SessionRecording>,
+    auditor: CommandAuditor,
 ) {
     while let Some(msg) = stream.next().await {
         match msg {
             Ok(AggregatedMessage::Text(text)) => {
+                if let Some(message) = ControlMessage::parse(&text) {
+                    let result = apply_control_message(
+                        &mut tty_writer,
+                        &resizer,
+                        message,
+                        recording.as_ref(),
+                        &auditor,
+                    )
+                    .await;
+
+                    if let Err(err) = result {
+                        log::error!(
+                            "WS → TTY: Failed to apply control message: {}",
+                            err.to_str()
+                        );
+                        return;
+                    }
+
+                    continue;
+                }
+
+                if let Some(recording) = &recording {
+                    recording.input(text.as_bytes()).await;
+                }
+
+                auditor.input(text.as_bytes());
+
                 if let Err(err) = tty_writer.write_all(text.as_bytes()).await {
                     log::error!("WS → TTY: Failed to write text: {}", err);
                     return;
@@ -45,6 +159,12 @@ async fn relay_messages_from_user_to_client(
             }
 
             Ok(AggregatedMessage::Binary(bin)) => {
+                if let Some(recording) = &recording {
+                    recording.input(&bin).await;
+                }
+
+                auditor.input(&bin);
+
                 if let Err(err) = tty_writer.write_all(&bin).await {
                     log::error!("WS → TTY: Failed to write binary: {}", err);
                     return;
@@ -76,9 +196,52 @@ async fn relay_messages_from_user_to_client(
     log::info!("WS → SSH: WebSocket stream closed.");
 }
 
+async fn apply_control_message(
+    tty_writer: &mut WriteHalf<TunnelStream>,
+    resizer: &TtyResizer,
+    message: ControlMessage,
+    recording: Option<&SessionRecording>,
+    auditor: &CommandAuditor,
+) -> Result<(), Error> {
+    match message {
+        ControlMessage::Resize(size) => {
+            log::debug!("WS → TTY: Resizing terminal to {}x{}", size.cols, size.rows);
+
+            if let Some(recording) = recording {
+                recording.resize(size).await;
+            }
+
+            // A device unable to resize keeps working with the previous size.
+            if let Err(err) = resizer.resize(size).await {
+                log::warn!("WS → TTY: Failed to resize terminal: {}", err.to_str());
+            }
+
+            Ok(())
+        }
+        ControlMessage::Signal(signal) => {
+            let input = [signal.control_character()];
+
+            if let Some(recording) = recording {
+                recording.input(&input).await;
+            }
+
+            auditor.input(&input);
+
+            tty_writer.write_all(&input).await.handle_err(location!())
+        }
+        ControlMessage::Keepalive => Ok(()),
+        ControlMessage::Unsupported(reason) => {
+            log::warn!("WS → TTY: Ignored control message: {}", reason);
+            Ok(())
+        }
+    }
+}
+
 async fn relay_messages_from_ssh_to_client(
     mut ws_session: WSSession,
-    mut tty_reader: ReadHalf<TcpStream>,
+    mut tty_reader: ReadHalf<TunnelStream>,
+    recording: Option<SessionRecording>,
+    auditor: CommandAuditor,
 ) {
     loop {
         let mut buf = [0u8; 8196];
@@ -88,6 +251,12 @@ async fn relay_messages_from_ssh_to_client(
                 break;
             }
             Ok(n) => {
+                if let Some(recording) = &recording {
+                    recording.output(&buf[..n]).await;
+                }
+
+                auditor.output(&buf[..n]);
+
                 let binmsg = Bytes::copy_from_slice(&buf[..n]);
                 if let Err(err) = ws_session.binary(binmsg).await {
                     log::error!(
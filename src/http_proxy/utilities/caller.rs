@@ -0,0 +1,196 @@
+//! Authenticated callers of the `/wallguard/api/v1/*` routes.
+//!
+//! Every API handler takes a [`Caller`], extracted from the bearer token of the request.
+//! The datastore still verifies the token on every operation; the caller is used for the
+//! checks the datastore can't make on its own:
+//! - the role of the caller, mapped from the role id of its account, which decides what it may do,
+//! - the organization of the caller, which must own the devices it acts on.
+
+use crate::datastore::Device;
+use crate::http_proxy::config::HttpProxyConfig;
+use crate::http_proxy::utilities::authorization;
+use crate::http_proxy::utilities::error_json::ErrorJson;
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::web::Data;
+use actix_web::{FromRequest, HttpRequest, HttpResponse};
+use nullnet_libtoken::Token;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::{Ready, ready};
+
+/// Roles of the callers, each one granting the permissions of the previous ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// May list and inspect devices.
+    Viewer,
+    /// May open remote access sessions and change what devices monitor and enforce.
+    Operator,
+    /// May authorize devices and change how they are reached.
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl TryFrom<&str> for Role {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_ascii_lowercase().as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Unsupported role: {value}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub jwt: String,
+    pub account_id: String,
+    pub organization_id: String,
+    pub role: Role,
+}
+
+impl Caller {
+    /// Fails with `403 Forbidden` unless the caller has at least the given role.
+    pub fn require(&self, role: Role) -> Result<(), HttpResponse> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err(HttpResponse::Forbidden().json(ErrorJson::from(format!(
+                "This operation requires the {} role",
+                role.as_str()
+            ))))
+        }
+    }
+
+    /// Fails with `404 Not Found` unless the device belongs to the organization of the caller.
+    ///
+    /// Devices of other organizations are reported as missing, so that their ids can't be probed.
+    pub fn ensure_device_access(&self, device: &Device) -> Result<(), HttpResponse> {
+        if device.organization == self.organization_id {
+            Ok(())
+        } else {
+            Err(HttpResponse::NotFound().json(ErrorJson::from("Device not found")))
+        }
+    }
+
+    fn from_request(request: &HttpRequest) -> Result<Self, HttpResponse> {
+        let Some(jwt) = authorization::extract_authorization_token(request) else {
+            return Err(
+                HttpResponse::Unauthorized().json(ErrorJson::from("Missing Authorization header"))
+            );
+        };
+
+        let Ok(token) = Token::from_jwt(&jwt) else {
+            return Err(HttpResponse::Unauthorized().json(ErrorJson::from("Malformed JWT token")));
+        };
+
+        let Some(role) = request
+            .app_data::<Data<HttpProxyConfig>>()
+            .and_then(|config| resolve_role(&config.roles, &token))
+        else {
+            return Err(HttpResponse::Forbidden().json(ErrorJson::from("Caller has no role")));
+        };
+
+        Ok(Self {
+            jwt,
+            account_id: token.account.account_id,
+            organization_id: token.account.organization_id,
+            role,
+        })
+    }
+}
+
+impl FromRequest for Caller {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            Caller::from_request(request)
+                .map_err(|response| InternalError::from_response("", response).into()),
+        )
+    }
+}
+
+/// Maps the datastore role id of the account of a token to the role it grants, if any.
+///
+/// The signature of the token is not checked, see the module documentation.
+fn resolve_role(roles: &HashMap<String, Role>, token: &Token) -> Option<Role> {
+    let role_id = token.account.role_id.as_ref()?;
+    roles.get(role_id).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    fn token(role_id: Option<&str>) -> Token {
+        let claims = serde_json::json!({
+            "account": {
+                "organization": {
+                    "id": "org-id",
+                    "name": "Organization",
+                    "code": "ORG",
+                    "categories": [],
+                    "status": "Active",
+                    "organization_id": "org-id",
+                    "parent_organization_id": null
+                },
+                "id": "account-id",
+                "account_id": "account@example.com",
+                "organization_id": "org-id",
+                "account_status": "Active",
+                "role_id": role_id
+            },
+            "iat": 1_700_000_000,
+            "exp": 1_700_003_600
+        });
+
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let jwt = format!("eyJhbGciOiJIUzI1NiJ9.{payload}.signature");
+
+        Token::from_jwt(&jwt).expect("Failed to parse the token")
+    }
+
+    #[test]
+    fn resolves_role_from_role_id() {
+        let roles = HashMap::from([
+            (String::from("role-operator"), Role::Operator),
+            (String::from("role-admin"), Role::Admin),
+        ]);
+
+        assert_eq!(
+            resolve_role(&roles, &token(Some("role-admin"))),
+            Some(Role::Admin)
+        );
+        assert_eq!(
+            resolve_role(&roles, &token(Some("role-operator"))),
+            Some(Role::Operator)
+        );
+        assert_eq!(resolve_role(&roles, &token(Some("role-unknown"))), None);
+        assert_eq!(resolve_role(&roles, &token(None)), None);
+    }
+
+    #[test]
+    fn roles_are_ordered() {
+        assert!(Role::Admin > Role::Operator);
+        assert!(Role::Operator > Role::Viewer);
+        assert_eq!(Role::try_from("Admin"), Ok(Role::Admin));
+        assert!(Role::try_from("root").is_err());
+    }
+}
@@ -1,4 +1,7 @@
 pub mod authorization;
+pub mod caller;
 pub mod error_json;
 pub mod request_handling;
+pub mod ssh_keys;
+pub mod terminal;
 pub mod tunneling;
@@ -1,15 +1,25 @@
 use crate::app_context::AppContext;
+use crate::datastore::RemoteAccessRecording;
 use crate::datastore::RemoteAccessSession;
 use crate::datastore::RemoteAccessType;
 use crate::datastore::SSHKeypair;
 use crate::http_proxy::utilities::authorization;
 use crate::http_proxy::utilities::error_json::ErrorJson;
+use crate::http_proxy::utilities::ssh_keys;
+use crate::http_proxy::utilities::terminal::TerminalSize;
+use crate::session_recording::SessionRecording;
 use actix_web::HttpRequest;
 use actix_web::HttpResponse;
+use actix_web::http::header::{HeaderName, HeaderValue};
 use actix_web::web::Payload;
 use actix_ws::{MessageStream, Session as WSSession};
+use chrono::Utc;
 use nullnet_libtoken::Token;
 use std::sync::Arc;
+use std::time::Duration;
+
+/// Number of times a use of a session is recorded before giving up on concurrent uses.
+const MAX_CONSUME_ATTEMPTS: usize = 3;
 
 pub fn extract_session_token(req: &HttpRequest) -> Result<String, HttpResponse> {
     authorization::extract_proxy_session_token(req).ok_or_else(|| {
@@ -24,22 +34,93 @@ pub async fn fetch_token(ctx: &AppContext) -> Result<Arc<Token>, HttpResponse> {
     })
 }
 
+/// Fetches a remote access session, making sure it can still be used.
+///
+/// Expired and used up sessions are rejected with `410 Gone`.
+///
+/// The session token is the only credential checked here: browser navigations and
+/// WebSocket upgrades to `<session>.<domain>` carry no `Authorization` header, so the
+/// caller can't be matched against the owner of the session. Session tokens are random,
+/// short-lived and revocable, and must be handed out to the owner only.
 pub async fn fetch_session(
     ctx: &AppContext,
     jwt: &str,
     session_token: &str,
 ) -> Result<RemoteAccessSession, HttpResponse> {
-    match ctx.datastore.obtain_session(jwt, session_token).await {
-        Ok(Some(sess)) => Ok(sess),
-        Ok(None) => Err(HttpResponse::NotFound().json(ErrorJson::from(format!(
-            "No session with token {}",
-            session_token
-        )))),
+    let session = match ctx.datastore.obtain_session(jwt, session_token).await {
+        Ok(Some(sess)) => sess,
+        Ok(None) => {
+            return Err(HttpResponse::NotFound().json(ErrorJson::from(format!(
+                "No session with token {}",
+                session_token
+            ))));
+        }
         Err(_) => {
-            Err(HttpResponse::InternalServerError()
-                .json(ErrorJson::from("Datastore operation failed")))
+            return Err(HttpResponse::InternalServerError()
+                .json(ErrorJson::from("Datastore operation failed")));
         }
+    };
+
+    if session.is_expired() {
+        return Err(HttpResponse::Gone().json(ErrorJson::from("Session has expired")));
+    }
+
+    if session.is_used_up() {
+        return Err(HttpResponse::Gone().json(ErrorJson::from("Session has been used up")));
+    }
+
+    Ok(session)
+}
+
+/// Records a use of the session, once a connection through it has been accepted.
+///
+/// The use count is only incremented if no other connection changed it in the meantime,
+/// so that concurrent connections can't exceed `max_uses`. Connections beyond it are
+/// rejected with `410 Gone`.
+pub async fn consume_session(
+    ctx: &AppContext,
+    jwt: &str,
+    session: &RemoteAccessSession,
+) -> Result<(), HttpResponse> {
+    let token = ctx.sysdev_token_provider.get().await.map_err(|_| {
+        HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Server error, can't obtain sysdev token"))
+    })?;
+
+    let mut uses = session.uses;
+
+    for _ in 0..MAX_CONSUME_ATTEMPTS {
+        if session.max_uses.is_some_and(|max_uses| uses >= max_uses) {
+            return Err(HttpResponse::Gone().json(ErrorJson::from("Session has been used up")));
+        }
+
+        match ctx
+            .datastore
+            .increment_session_uses(&token.jwt, &session.id, uses)
+            .await
+        {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(_) => {
+                return Err(HttpResponse::InternalServerError()
+                    .json(ErrorJson::from("Datastore operation failed")));
+            }
+        }
+
+        // Another connection used the session concurrently, or it was revoked.
+        uses = match ctx.datastore.obtain_session(jwt, &session.token).await {
+            Ok(Some(current)) => current.uses,
+            Ok(None) => {
+                return Err(HttpResponse::Gone().json(ErrorJson::from("Session has been revoked")));
+            }
+            Err(_) => {
+                return Err(HttpResponse::InternalServerError()
+                    .json(ErrorJson::from("Datastore operation failed")));
+            }
+        };
     }
+
+    Err(HttpResponse::Conflict().json(ErrorJson::from("Session is in use, try again")))
 }
 
 pub fn ensure_session_type(
@@ -53,21 +134,18 @@ pub fn ensure_session_type(
     }
 }
 
+/// Fetches the SSH keypair of a device, rotating it if it expired since the session was requested.
 pub async fn fetch_ssh_keypair(
     ctx: &AppContext,
     jwt: &str,
     device_id: &str,
+    ttl: Duration,
 ) -> Result<SSHKeypair, HttpResponse> {
-    match ctx.datastore.obtain_ssh_keypair(jwt, device_id).await {
-        Ok(Some(keypair)) => Ok(keypair),
-        Ok(None) => {
-            Err(HttpResponse::NotFound().json(ErrorJson::from("No SSH keys have been found")))
-        }
-        Err(_) => {
-            Err(HttpResponse::InternalServerError()
-                .json(ErrorJson::from("Datastore operation failed")))
-        }
-    }
+    ssh_keys::ensure_keypair(ctx, jwt, device_id, ttl)
+        .await
+        .map_err(|_| {
+            HttpResponse::InternalServerError().json(ErrorJson::from("Failed to obtain SSH keys"))
+        })
 }
 
 pub fn upgrade_to_websocket(
@@ -77,3 +155,73 @@ pub fn upgrade_to_websocket(
     actix_ws::handle(&request, body)
         .map_err(|err| HttpResponse::InternalServerError().json(ErrorJson::from(err.to_string())))
 }
+
+/// Starts recording a terminal session, if recording is enabled.
+///
+/// The recording is attributed to the owner of the session, and listed under it so that
+/// it can be found for playback. Sessions that can't be recorded are refused rather
+/// than left unrecorded.
+pub async fn start_recording(
+    ctx: &AppContext,
+    session: &RemoteAccessSession,
+    size: Option<TerminalSize>,
+) -> Result<Option<SessionRecording>, HttpResponse> {
+    let Some(recorder) = &ctx.recorder else {
+        return Ok(None);
+    };
+
+    let token = ctx.sysdev_token_provider.get().await.map_err(|_| {
+        HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Server error, can't obtain sysdev token"))
+    })?;
+
+    let started_at = Utc::now().to_rfc3339();
+
+    let recording = recorder
+        .start(
+            &session.device_id,
+            session.owner.clone(),
+            session.r#type,
+            size,
+        )
+        .await
+        .map_err(|_| {
+            HttpResponse::InternalServerError()
+                .json(ErrorJson::from("Failed to start session recording"))
+        })?;
+
+    let record = RemoteAccessRecording {
+        recording_id: recording.id().to_owned(),
+        device_id: session.device_id.clone(),
+        remote_access_session_id: session.id.clone(),
+        session_type: session.r#type.as_str().to_owned(),
+        user_id: session.owner.clone(),
+        started_at,
+    };
+
+    if ctx
+        .datastore
+        .create_recording(&token.jwt, &record)
+        .await
+        .is_err()
+    {
+        recording.finish().await;
+        return Err(HttpResponse::InternalServerError()
+            .json(ErrorJson::from("Failed to store session recording")));
+    }
+
+    Ok(Some(recording))
+}
+
+/// Tells non-browser clients under which id the session is recorded, since browsers
+/// can't read the headers of a WebSocket handshake.
+pub fn set_recording_header(response: &mut HttpResponse, recording: Option<&SessionRecording>) {
+    let Some(value) = recording.and_then(|recording| HeaderValue::from_str(recording.id()).ok())
+    else {
+        return;
+    };
+
+    response
+        .headers_mut()
+        .insert(HeaderName::from_static("x-recording-id"), value);
+}
@@ -0,0 +1,63 @@
+//! Lifecycle of the keypairs the SSH gateway logs into devices with.
+//!
+//! Keys are not installed on devices once and for all: the public key of the current keypair
+//! is pushed to the device with every `OpenSshSessionCommand`. A rotated keypair is thus
+//! used from the next SSH session on, and the previous one stops being accepted.
+
+use crate::app_context::AppContext;
+use crate::datastore::SSHKeypair;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use std::time::Duration;
+
+/// Returns the current keypair of the device, generating a new one if it has none
+/// or if it expired.
+pub async fn ensure_keypair(
+    ctx: &AppContext,
+    jwt: &str,
+    device_id: &str,
+    ttl: Duration,
+) -> Result<SSHKeypair, Error> {
+    match ctx.datastore.obtain_ssh_keypair(jwt, device_id).await? {
+        Some(keypair) if !keypair.is_expired() => Ok(keypair),
+        previous => rotate_keypair(ctx, jwt, device_id, previous, ttl).await,
+    }
+}
+
+/// Replaces the keypair of the device with a newly generated one.
+///
+/// The pinned host key belongs to the device, so it is carried over to the new keypair.
+pub async fn rotate_keypair(
+    ctx: &AppContext,
+    jwt: &str,
+    device_id: &str,
+    previous: Option<SSHKeypair>,
+    ttl: Duration,
+) -> Result<SSHKeypair, Error> {
+    let mut keypair = SSHKeypair::generate(device_id, ttl).await?;
+    keypair.host_key_fingerprint = previous.and_then(|previous| previous.host_key_fingerprint);
+
+    ctx.datastore.create_ssh_keypair(jwt, &keypair).await?;
+
+    // The record id is assigned by the datastore.
+    let keypair = ctx
+        .datastore
+        .obtain_ssh_keypair(jwt, device_id)
+        .await?
+        .ok_or("Generated SSH keypair not found")
+        .handle_err(location!())?;
+
+    if let Err(err) = ctx
+        .datastore
+        .delete_ssh_keypairs_except(jwt, device_id, &keypair.id)
+        .await
+    {
+        log::warn!(
+            "Failed to delete rotated SSH keypairs of device {device_id}: {}",
+            err.to_str()
+        );
+    }
+
+    log::info!("Rotated SSH keypair of device {device_id}");
+
+    Ok(keypair)
+}
@@ -0,0 +1,145 @@
+//! Control protocol of the terminal WebSockets, shared by the SSH and TTY gateways.
+//!
+//! Binary frames carry raw terminal input. Text frames carry raw input as well, unless
+//! they hold one of the JSON control messages below:
+//! - `{"type": "resize", "cols": 120, "rows": 40}` resizes the remote terminal,
+//! - `{"type": "signal", "name": "INT"}` sends a signal to the foreground process,
+//! - `{"type": "keepalive"}` keeps the connection open through idle intermediaries.
+//!
+//! The initial size of the terminal can be given in the query string of the gateway URL,
+//! e.g. `/wallguard/gateway/ssh?cols=120&rows=40`.
+
+use actix_web::HttpRequest;
+use actix_web::web::Query;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct TerminalSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl TerminalSize {
+    /// Reads the initial terminal size from the query string, if any.
+    pub fn from_query(request: &HttpRequest) -> Option<Self> {
+        Query::<Self>::from_query(request.query_string())
+            .ok()
+            .map(Query::into_inner)
+            .filter(TerminalSize::is_valid)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.cols > 0 && self.rows > 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Quit,
+    Suspend,
+}
+
+impl Signal {
+    /// Returns the control character making the terminal line discipline
+    /// deliver the signal to the foreground process.
+    pub fn control_character(&self) -> u8 {
+        match self {
+            Signal::Interrupt => 0x03,
+            Signal::Quit => 0x1c,
+            Signal::Suspend => 0x1a,
+        }
+    }
+}
+
+impl TryFrom<&str> for Signal {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value = value.to_ascii_uppercase();
+
+        match value.strip_prefix("SIG").unwrap_or(&value) {
+            "INT" => Ok(Signal::Interrupt),
+            "QUIT" => Ok(Signal::Quit),
+            "TSTP" => Ok(Signal::Suspend),
+            _ => Err(format!("Unsupported signal: {value}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlMessage {
+    Resize(TerminalSize),
+    Signal(Signal),
+    Keepalive,
+    /// A well-formed control message that can't be honored, e.g. an unknown signal.
+    Unsupported(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum RawControlMessage {
+    Resize { cols: u16, rows: u16 },
+    Signal { name: String },
+    Keepalive,
+}
+
+impl ControlMessage {
+    /// Parses a text frame, returning `None` if it is terminal input.
+    pub fn parse(text: &str) -> Option<Self> {
+        if !text.starts_with('{') {
+            return None;
+        }
+
+        match serde_json::from_str::<RawControlMessage>(text).ok()? {
+            RawControlMessage::Resize { cols, rows } => {
+                let size = TerminalSize { cols, rows };
+                size.is_valid().then_some(ControlMessage::Resize(size))
+            }
+            RawControlMessage::Signal { name } => match Signal::try_from(name.as_str()) {
+                Ok(signal) => Some(ControlMessage::Signal(signal)),
+                Err(err) => Some(ControlMessage::Unsupported(err)),
+            },
+            RawControlMessage::Keepalive => Some(ControlMessage::Keepalive),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_control_messages() {
+        assert_eq!(
+            ControlMessage::parse(r#"{"type":"resize","cols":120,"rows":40}"#),
+            Some(ControlMessage::Resize(TerminalSize {
+                cols: 120,
+                rows: 40
+            }))
+        );
+        assert_eq!(
+            ControlMessage::parse(r#"{"type":"signal","name":"SIGINT"}"#),
+            Some(ControlMessage::Signal(Signal::Interrupt))
+        );
+        assert_eq!(
+            ControlMessage::parse(r#"{"type":"keepalive"}"#),
+            Some(ControlMessage::Keepalive)
+        );
+        assert!(matches!(
+            ControlMessage::parse(r#"{"type":"signal","name":"KILL"}"#),
+            Some(ControlMessage::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn treats_other_text_as_input() {
+        assert_eq!(ControlMessage::parse("ls -la\r"), None);
+        assert_eq!(ControlMessage::parse("{"), None);
+        assert_eq!(ControlMessage::parse(r#"{"type":"exec"}"#), None);
+        assert_eq!(
+            ControlMessage::parse(r#"{"type":"resize","cols":0,"rows":0}"#),
+            None
+        );
+    }
+}
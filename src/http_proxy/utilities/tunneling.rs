@@ -1,7 +1,9 @@
 use crate::app_context::AppContext;
+use crate::datastore::{DeviceUiSettings, RemoteAccessType, UiProtocol};
+use crate::orchestrator::TunnelGuard;
+use crate::reverse_tunnel::TunnelStream;
 use nullnet_liberror::{Error, ErrorHandler, Location, location};
 use std::time::Duration;
-use tokio::net::TcpStream;
 
 /// Timeout for awaiting the reverse tunnel connection
 const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1_000);
@@ -10,9 +12,12 @@ const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1_000);
 /// Variants may contain protocol-specific configuration.
 #[derive(Debug, Clone)]
 enum TunnelType {
-    Ssh(String),
+    Ssh {
+        public_key: String,
+        login_user: String,
+    },
     Tty,
-    UI(String),
+    UI(UiProtocol, u16),
 }
 
 /// Establishes a tunneled SSH connection for a device using its `SSHKeypair`.
@@ -21,38 +26,50 @@ enum TunnelType {
 /// - `context`: The application context with orchestrator and tunnel services
 /// - `device_uuid`: The device UUID
 /// - `public_key`: The SSH public key used for authentication
+/// - `login_user`: The device user the public key is to be authorized for
 pub async fn establish_tunneled_ssh(
     context: &AppContext,
     device_uuid: &str,
     public_key: &str,
-) -> Result<TcpStream, Error> {
-    establish_tunneled_channel(context, device_uuid, TunnelType::Ssh(public_key.into())).await
+    login_user: &str,
+) -> Result<(TunnelStream, TunnelGuard), Error> {
+    let r#type = TunnelType::Ssh {
+        public_key: public_key.into(),
+        login_user: login_user.into(),
+    };
+    let (stream, guard, _) = establish_tunneled_channel(context, device_uuid, r#type).await?;
+    Ok((stream, guard))
 }
 
 /// Establishes a tunneled TTY (terminal) connection to the specified device.
 ///
+/// Also returns the token of the tunnel, which identifies the session
+/// in later commands such as `Client::resize_tty`.
+///
 /// # Arguments
 /// - `context`: The application context
 /// - `device_uuid`: The device UUID
 pub async fn establish_tunneled_tty(
     context: &AppContext,
     device_uuid: &str,
-) -> Result<TcpStream, Error> {
+) -> Result<(TunnelStream, TunnelGuard, String), Error> {
     establish_tunneled_channel(context, device_uuid, TunnelType::Tty).await
 }
 
-/// Establishes a tunneled UI session using a given protocol string.
+/// Establishes a tunneled UI session to the web UI described by `settings`.
 ///
 /// # Arguments
 /// - `context`: The application context
 /// - `device_uuid`: The device UUID
-/// - `protocol`: The UI protocol string (to be replaced with enum in future)
+/// - `settings`: The UI settings of the device
 pub async fn establish_tunneled_ui(
     context: &AppContext,
     device_uuid: &str,
-    protocol: &str,
-) -> Result<TcpStream, Error> {
-    establish_tunneled_channel(context, device_uuid, TunnelType::UI(protocol.into())).await
+    settings: &DeviceUiSettings,
+) -> Result<(TunnelStream, TunnelGuard), Error> {
+    let r#type = TunnelType::UI(settings.protocol, settings.port);
+    let (stream, guard, _) = establish_tunneled_channel(context, device_uuid, r#type).await?;
+    Ok((stream, guard))
 }
 
 /// Core handler that establishes a tunneled channel of the given `TunnelType`.
@@ -60,13 +77,16 @@ pub async fn establish_tunneled_ui(
 /// It retrieves a reverse tunnel token, sends a tunnel request to the orchestrator client,
 /// and awaits the resulting connection with a timeout.
 ///
+/// The returned `TunnelGuard` keeps the tunnel listed among the client's open tunnels
+/// and must be held for as long as the stream is in use. The tunnel token is returned too.
+///
 /// # Errors
 /// Returns an error if the client is not connected, request fails, or connection times out.
 async fn establish_tunneled_channel(
     context: &AppContext,
     device_uuid: &str,
     r#type: TunnelType,
-) -> Result<TcpStream, Error> {
+) -> Result<(TunnelStream, TunnelGuard, String), Error> {
     let client = context
         .orchestractor
         .get_client(device_uuid)
@@ -78,19 +98,35 @@ async fn establish_tunneled_channel(
 
     let (token, receiver) = context.tunnel.expect_connection().await;
 
-    match r#type {
-        TunnelType::Ssh(public_key) => {
+    let access_type = match r#type {
+        TunnelType::Ssh { .. } => RemoteAccessType::Ssh,
+        TunnelType::Tty => RemoteAccessType::Tty,
+        TunnelType::UI(..) => RemoteAccessType::Ui,
+    };
+
+    // The incoming tunnel connection acts as the acknowledgement here,
+    // so the command result itself is not awaited.
+    let _ = match r#type {
+        TunnelType::Ssh {
+            public_key,
+            login_user,
+        } => {
             client
-                .request_ssh_session(token.clone(), public_key)
+                .request_ssh_session(token.clone(), public_key, login_user)
                 .await?
         }
         TunnelType::Tty => client.request_tty_session(token.clone()).await?,
-        TunnelType::UI(protocol) => client.request_ui_session(token.clone(), protocol).await?,
+        TunnelType::UI(protocol, port) => {
+            client
+                .request_ui_session(token.clone(), protocol.as_str(), port)
+                .await?
+        }
     };
 
     tokio::select! {
         stream = receiver => {
-            stream.handle_err(location!())
+            let stream = stream.handle_err(location!())?;
+            Ok((stream, client.track_tunnel(access_type), token.into()))
         }
         _ = tokio::time::sleep(DEFAULT_TIMEOUT) => {
             context.tunnel.cancel_expectation(&token).await;
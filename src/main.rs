@@ -3,12 +3,15 @@ use control_service::run_control_service;
 use http_proxy::run_http_proxy;
 
 mod app_context;
+mod command_audit;
 mod control_service;
 mod datastore;
 mod http_proxy;
 mod orchestrator;
 mod protocol;
 mod reverse_tunnel;
+mod session_recording;
+mod shutdown;
 mod token_provider;
 mod traffic_handler;
 mod utilities;
@@ -22,9 +25,14 @@ async fn main() {
         std::process::exit(1);
     });
 
+    let control_service = tokio::spawn(run_control_service(app_context.clone()));
+    let http_proxy = tokio::spawn(run_http_proxy(app_context.clone()));
+
     tokio::select! {
-        _ = tokio::signal::ctrl_c() => {},
-        _ = run_control_service(app_context.clone()) => {},
-        _ = run_http_proxy(app_context) => {}
+        _ = shutdown::wait_for_signal() => {},
+        _ = control_service => {},
+        _ = http_proxy => {}
     }
+
+    shutdown::graceful_shutdown(&app_context).await;
 }
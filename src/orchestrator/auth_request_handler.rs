@@ -11,12 +11,14 @@
 
 use crate::app_context::AppContext;
 use crate::datastore::Device;
-use crate::orchestrator::client::{Client, InboundStream, OutboundStream};
+use crate::orchestrator::client::{Client, InboundStream, OutboundStream, PeerCertificates};
 use crate::protocol::wallguard_commands::server_message::Message;
 use crate::protocol::wallguard_commands::{
     AuthenticationData, AuthorizationRequest, ServerMessage,
 };
 use crate::utilities;
+use crate::utilities::certificates;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tonic::Status;
@@ -31,11 +33,36 @@ macro_rules! fail_with_status {
 
 pub struct AuthReqHandler {
     context: AppContext,
+    remote_addr: Option<SocketAddr>,
+    peer_certs: Option<PeerCertificates>,
 }
 
 impl AuthReqHandler {
-    pub fn new(context: AppContext) -> Self {
-        Self { context }
+    pub fn new(
+        context: AppContext,
+        remote_addr: Option<SocketAddr>,
+        peer_certs: Option<PeerCertificates>,
+    ) -> Self {
+        Self {
+            context,
+            remote_addr,
+            peer_certs,
+        }
+    }
+
+    /// Whether the connection was authenticated with the client certificate issued to the device.
+    ///
+    /// Devices that were never issued a certificate are accepted as is.
+    fn presents_device_certificate(&self, device: &Device) -> bool {
+        let Some(expected) = &device.certificate_fingerprint else {
+            return true;
+        };
+
+        self.peer_certs
+            .as_ref()
+            .and_then(|certs| certs.first())
+            .map(|cert| certificates::fingerprint(cert.as_ref()))
+            .is_some_and(|presented| &presented == expected)
     }
 
     pub async fn handle(
@@ -60,6 +87,7 @@ impl AuthReqHandler {
             let _ = outbound
                 .send(Ok(ServerMessage {
                     message: Some(Message::AuthorizationRejectedMessage(())),
+                    ..Default::default()
                 }))
                 .await;
             return;
@@ -112,6 +140,18 @@ impl AuthReqHandler {
             device.os = auth.target_os;
             device.uuid = auth.uuid.clone();
 
+            let certificate = match &self.context.device_ca {
+                Some(device_ca) => match device_ca.issue(&device.id).await {
+                    Ok(certificate) => Some(certificate),
+                    Err(_) => fail_with_status!(outbound, "Failed to issue device certificate"),
+                },
+                None => None,
+            };
+
+            device.certificate_fingerprint = certificate
+                .as_ref()
+                .map(|certificate| certificate.fingerprint.clone());
+
             if self
                 .context
                 .datastore
@@ -150,6 +190,7 @@ impl AuthReqHandler {
                 installation_code.organization_id,
                 inbound,
                 outbound,
+                self.remote_addr,
                 self.context.clone(),
             )));
 
@@ -158,6 +199,11 @@ impl AuthReqHandler {
             authentication.app_id = Some(account_id);
             authentication.app_secret = Some(account_secret);
 
+            if let Some(certificate) = certificate {
+                authentication.client_certificate = Some(certificate.certificate);
+                authentication.client_key = Some(certificate.private_key);
+            }
+
             if client.lock().await.authorize(authentication).await.is_ok() {
                 clients.insert(auth.uuid, client.clone());
             } else {
@@ -177,11 +223,22 @@ impl AuthReqHandler {
             if device.is_some() {
                 let device = device.unwrap();
 
+                if device.authorized && !self.presents_device_certificate(&device) {
+                    fail_with_status!(
+                        outbound,
+                        format!(
+                            "Device {} did not present its client certificate",
+                            auth.uuid
+                        )
+                    )
+                }
+
                 let client = Arc::new(Mutex::new(Client::new(
                     auth.uuid.clone(),
                     installation_code.organization_id,
                     inbound,
                     outbound,
+                    self.remote_addr,
                     self.context.clone(),
                 )));
 
@@ -223,6 +280,7 @@ impl AuthReqHandler {
                     installation_code.organization_id,
                     inbound,
                     outbound,
+                    self.remote_addr,
                     self.context.clone(),
                 )));
 
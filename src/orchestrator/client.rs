@@ -1,26 +1,57 @@
+use chrono::{DateTime, Utc};
 use nullnet_liberror::{Error, ErrorHandler, Location, location};
-
-use tokio::sync::mpsc;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
 use tonic::Status;
 use tonic::Streaming;
+use tonic::transport::CertificateDer;
 
 use crate::app_context::AppContext;
+use crate::datastore::RemoteAccessType;
 use crate::orchestrator::control_stream::control_stream;
+use crate::orchestrator::health::ConnectionHealth;
+use crate::orchestrator::pending_commands::{PendingCommand, PendingCommands};
+use crate::orchestrator::tunnels::{OpenTunnels, TunnelGuard, TunnelInfo};
 use crate::protocol::wallguard_commands::AuthenticationData;
 use crate::protocol::wallguard_commands::ClientMessage;
+use crate::protocol::wallguard_commands::ConfigurationData;
 use crate::protocol::wallguard_commands::ServerMessage;
+use crate::protocol::wallguard_commands::ServerShutdownData;
 use crate::protocol::wallguard_commands::SshSessionData;
+use crate::protocol::wallguard_commands::TtyResizeData;
 use crate::protocol::wallguard_commands::UiSessionData;
 use crate::protocol::wallguard_commands::server_message::Message;
 
 pub(crate) type OutboundStream = mpsc::Sender<Result<ServerMessage, Status>>;
 pub(crate) type InboundStream = Streaming<ClientMessage>;
+pub(crate) type PeerCertificates = Arc<Vec<CertificateDer<'static>>>;
+
+/// Snapshot of the live state of a connected client.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientInfo {
+    pub connected_since: String,
+    pub last_heartbeat: Option<String>,
+    pub last_seen: Option<String>,
+    pub latency_ms: Option<u128>,
+    pub missed_heartbeats: u32,
+    pub remote_address: Option<String>,
+    pub open_tunnels: Vec<TunnelInfo>,
+}
 
 #[derive(Debug)]
 pub struct Client {
     uuid: String,
     _org_id: String,
     outbound: OutboundStream,
+    pending: PendingCommands,
+    health: ConnectionHealth,
+    tunnels: OpenTunnels,
+    revoked: watch::Sender<bool>,
+    remote_addr: Option<SocketAddr>,
+    connected_since: DateTime<Utc>,
 }
 
 impl Client {
@@ -29,42 +60,83 @@ impl Client {
         org_id: String,
         inbound: InboundStream,
         outbound: OutboundStream,
+        remote_addr: Option<SocketAddr>,
         context: AppContext,
     ) -> Self {
+        let pending = PendingCommands::default();
+        let health = ConnectionHealth::default();
+        let (revoked, revoked_rx) = watch::channel(false);
+
         tokio::spawn(control_stream(
             uuid.clone(),
             inbound,
             outbound.clone(),
+            pending.clone(),
+            health.clone(),
+            revoked_rx,
             context,
         ));
 
         Self {
             uuid,
             outbound,
+            pending,
+            health,
+            tunnels: OpenTunnels::default(),
+            revoked,
+            remote_addr,
+            connected_since: Utc::now(),
             _org_id: org_id,
         }
     }
 
-    pub async fn authorize(&mut self, data: AuthenticationData) -> Result<(), Error> {
-        log::debug!("Authorizing device {}", self.uuid);
+    /// Returns a snapshot of the client's connection state.
+    pub fn info(&self) -> ClientInfo {
+        ClientInfo {
+            connected_since: self.connected_since.to_rfc3339(),
+            last_heartbeat: self.health.last_heartbeat().map(|time| time.to_rfc3339()),
+            last_seen: self.health.last_seen().map(|time| time.to_rfc3339()),
+            latency_ms: self.health.latency().map(|latency| latency.as_millis()),
+            missed_heartbeats: self.health.missed_heartbeats(),
+            remote_address: self.remote_addr.map(|addr| addr.to_string()),
+            open_tunnels: self.tunnels.list(),
+        }
+    }
+
+    /// Registers a tunnel of the given type as open for this client.
+    pub fn track_tunnel(&self, r#type: RemoteAccessType) -> TunnelGuard {
+        self.tunnels.open(r#type)
+    }
+
+    pub(crate) fn tunnels(&self) -> OpenTunnels {
+        self.tunnels.clone()
+    }
 
+    /// Tells the client that the server is going away and when it should reconnect.
+    ///
+    /// This is a notification rather than a command, so no result is expected. The message
+    /// is not queued behind a congested outbound stream, since the server is about to exit anyway.
+    pub fn notify_shutdown(&self, reconnect_after: Duration) -> Result<(), Error> {
         let message = ServerMessage {
-            message: Some(Message::DeviceAuthorizedMessage(data)),
+            message: Some(Message::ServerShutdownMessage(ServerShutdownData {
+                reconnect_after_secs: reconnect_after.as_secs() as u32,
+            })),
+            ..Default::default()
         };
 
-        self.outbound
-            .send(Ok(message))
-            .await
-            .handle_err(location!())?;
-
-        Ok(())
+        self.outbound.try_send(Ok(message)).handle_err(location!())
     }
 
-    pub async fn _deauthorize(&mut self) -> Result<(), Error> {
-        log::debug!("Deauthorizing device {}", self.uuid);
+    /// Sends a command tagged with a new request id to the client.
+    ///
+    /// The returned `PendingCommand` can be awaited to learn whether the client
+    /// actually applied the command.
+    async fn send(&self, message: Message) -> Result<PendingCommand, Error> {
+        let pending = self.pending.register();
 
         let message = ServerMessage {
-            message: Some(Message::DeviceDeauthorizedMessage(())),
+            request_id: pending.request_id().to_string(),
+            message: Some(message),
         };
 
         self.outbound
@@ -72,62 +144,67 @@ impl Client {
             .await
             .handle_err(location!())?;
 
-        Ok(())
+        Ok(pending)
+    }
+
+    pub async fn authorize(&mut self, data: AuthenticationData) -> Result<PendingCommand, Error> {
+        log::debug!("Authorizing device {}", self.uuid);
+
+        self.revoked.send_replace(false);
+
+        self.send(Message::DeviceAuthorizedMessage(data)).await
     }
 
-    pub async fn enable_network_monitoring(&self, enable: bool) -> Result<(), Error> {
+    /// Notifies the client that it is no longer authorized, closes all of its open tunnels
+    /// and makes the control stream drop the client's credentials.
+    pub async fn deauthorize(&mut self) -> Result<PendingCommand, Error> {
+        log::debug!("Deauthorizing device {}", self.uuid);
+
+        self.revoked.send_replace(true);
+        self.tunnels.close_all();
+
+        self.send(Message::DeviceDeauthorizedMessage(())).await
+    }
+
+    pub async fn enable_network_monitoring(&self, enable: bool) -> Result<PendingCommand, Error> {
         log::info!(
             "Sending EnableNetworkMonitoringCommand to the client with device UUID {}",
             self.uuid
         );
 
-        let message = ServerMessage {
-            message: Some(Message::EnableNetworkMonitoringCommand(enable)),
-        };
-
-        self.outbound
-            .send(Ok(message))
+        self.send(Message::EnableNetworkMonitoringCommand(enable))
             .await
-            .handle_err(location!())
     }
 
-    pub async fn enable_telemetry_monitoring(&self, enable: bool) -> Result<(), Error> {
+    pub async fn enable_telemetry_monitoring(&self, enable: bool) -> Result<PendingCommand, Error> {
         log::info!(
             "Sending EnableTelemetryMonitoringCommand to the client with device UUID {}",
             self.uuid
         );
 
-        let message = ServerMessage {
-            message: Some(Message::EnableTelemetryMonitoringCommand(enable)),
-        };
-
-        self.outbound
-            .send(Ok(message))
+        self.send(Message::EnableTelemetryMonitoringCommand(enable))
             .await
-            .handle_err(location!())
     }
 
-    pub async fn enable_configuration_monitoring(&self, enable: bool) -> Result<(), Error> {
+    pub async fn enable_configuration_monitoring(
+        &self,
+        enable: bool,
+    ) -> Result<PendingCommand, Error> {
         log::info!(
             "Sending EnableConfigurationMonitoringCommand to the client with device UUID {}",
             self.uuid
         );
 
-        let message = ServerMessage {
-            message: Some(Message::EnableConfigurationMonitoringCommand(enable)),
-        };
-
-        self.outbound
-            .send(Ok(message))
+        self.send(Message::EnableConfigurationMonitoringCommand(enable))
             .await
-            .handle_err(location!())
     }
 
     pub async fn request_ssh_session(
         &self,
         tunnel_token: impl Into<String>,
         public_key: impl Into<String>,
-    ) -> Result<(), Error> {
+        login_user: impl Into<String>,
+    ) -> Result<PendingCommand, Error> {
         log::info!(
             "Sending OpenSshSessionCommandto to the client with device UUID {}",
             self.uuid
@@ -136,39 +213,53 @@ impl Client {
         let ssh_session_data = SshSessionData {
             tunnel_token: tunnel_token.into(),
             public_key: public_key.into(),
+            login_user: login_user.into(),
         };
 
-        let message: ServerMessage = ServerMessage {
-            message: Some(Message::OpenSshSessionCommand(ssh_session_data)),
-        };
-
-        self.outbound
-            .send(Ok(message))
+        self.send(Message::OpenSshSessionCommand(ssh_session_data))
             .await
-            .handle_err(location!())
     }
 
-    pub async fn request_tty_session(&self, tunnel_token: impl Into<String>) -> Result<(), Error> {
+    pub async fn request_tty_session(
+        &self,
+        tunnel_token: impl Into<String>,
+    ) -> Result<PendingCommand, Error> {
         log::info!(
             "Sending OpenTtySessionCommand to the client with device UUID {}",
             self.uuid
         );
 
-        let message = ServerMessage {
-            message: Some(Message::OpenTtySessionCommand(tunnel_token.into())),
+        self.send(Message::OpenTtySessionCommand(tunnel_token.into()))
+            .await
+    }
+
+    /// Resizes the terminal of the TTY session carried by the tunnel with the given token.
+    pub async fn resize_tty(
+        &self,
+        tunnel_token: impl Into<String>,
+        cols: u16,
+        rows: u16,
+    ) -> Result<PendingCommand, Error> {
+        log::debug!(
+            "Sending ResizeTtyCommand ({cols}x{rows}) to the client with device UUID {}",
+            self.uuid
+        );
+
+        let resize_data = TtyResizeData {
+            tunnel_token: tunnel_token.into(),
+            cols: cols.into(),
+            rows: rows.into(),
         };
 
-        self.outbound
-            .send(Ok(message))
-            .await
-            .handle_err(location!())
+        self.send(Message::ResizeTtyCommand(resize_data)).await
     }
 
     pub async fn request_ui_session(
         &self,
         tunnel_token: impl Into<String>,
         protocol: impl Into<String>,
-    ) -> Result<(), Error> {
+        port: u16,
+    ) -> Result<PendingCommand, Error> {
         log::info!(
             "Sending OpenUiSessionCommand to the client with device UUID {}",
             self.uuid
@@ -177,15 +268,31 @@ impl Client {
         let ui_session_data = UiSessionData {
             tunnel_token: tunnel_token.into(),
             protocol: protocol.into(),
+            port: port.into(),
         };
 
-        let message = ServerMessage {
-            message: Some(Message::OpenUiSessionCommand(ui_session_data)),
+        self.send(Message::OpenUiSessionCommand(ui_session_data))
+            .await
+    }
+
+    pub async fn apply_configuration(
+        &self,
+        configuration_id: impl Into<String>,
+        rules: impl Into<String>,
+        aliases: impl Into<String>,
+    ) -> Result<PendingCommand, Error> {
+        log::info!(
+            "Sending ApplyConfigurationCommand to the client with device UUID {}",
+            self.uuid
+        );
+
+        let configuration_data = ConfigurationData {
+            configuration_id: configuration_id.into(),
+            rules: rules.into(),
+            aliases: aliases.into(),
         };
 
-        self.outbound
-            .send(Ok(message))
+        self.send(Message::ApplyConfigurationCommand(configuration_data))
             .await
-            .handle_err(location!())
     }
 }
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct OrchestratorConfig {
+    /// Interval between two heartbeats sent to a client.
+    pub(crate) heartbeat_interval: Duration,
+    /// Number of consecutive unacknowledged heartbeats after which
+    /// the control stream is considered dead and torn down.
+    pub(crate) max_missed_heartbeats: u32,
+}
+
+impl OrchestratorConfig {
+    /// Constructs an `OrchestratorConfig` from the environment variables
+    /// `HEARTBEAT_INTERVAL_SECS` and `HEARTBEAT_MAX_MISSED`.
+    ///
+    /// Falls back to the default value of each setting that is missing or invalid.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        let heartbeat_interval = std::env::var("HEARTBEAT_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|v| *v > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(default.heartbeat_interval);
+
+        let max_missed_heartbeats = std::env::var("HEARTBEAT_MAX_MISSED")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(default.max_missed_heartbeats);
+
+        Self {
+            heartbeat_interval,
+            max_missed_heartbeats,
+        }
+    }
+}
+
+impl Default for OrchestratorConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: Duration::from_secs(20),
+            max_missed_heartbeats: 3,
+        }
+    }
+}
@@ -1,24 +1,33 @@
 use nullnet_liberror::{Error, ErrorHandler, Location, location};
 use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::watch;
 
 use crate::app_context::AppContext;
 use crate::orchestrator::client::{InboundStream, OutboundStream};
-use crate::protocol::wallguard_commands::{ServerMessage, client_message, server_message};
+use crate::orchestrator::config::OrchestratorConfig;
+use crate::orchestrator::health::ConnectionHealth;
+use crate::orchestrator::pending_commands::PendingCommands;
+use crate::protocol::wallguard_commands::{
+    ClientMessage, ServerMessage, client_message, server_message,
+};
 use crate::token_provider::TokenProvider;
 
-const HEARTBEAT_TIME: Duration = Duration::from_secs(20);
 const TOKEN_UPDATE_TIME: Duration = Duration::from_secs(60);
 
 pub(crate) async fn control_stream(
     device_uuid: String,
     inbound: InboundStream,
     outbound: OutboundStream,
+    pending: PendingCommands,
+    health: ConnectionHealth,
+    revoked: watch::Receiver<bool>,
     context: AppContext,
 ) {
     log::info!("Starting a control stream for device UUID {}", device_uuid);
 
     tokio::select! {
-        hres = healthcheck(outbound.clone()) => {
+        hres = healthcheck(outbound.clone(), health.clone(), context.orchestractor.config.clone()) => {
             if let Err(err) = hres {
                 log::error!(
                     "Health check for client with device UUID '{}' failed: {}",
@@ -27,7 +36,7 @@ pub(crate) async fn control_stream(
                 );
             }
         },
-        ares = authstream(inbound, outbound, context.clone()) => {
+        ares = authstream(inbound, outbound, pending.clone(), health, revoked, context.clone()) => {
             if let Err(err) = ares {
                 log::error!(
                     "Control stream for client with device UUID '{}' failed: {}",
@@ -38,10 +47,12 @@ pub(crate) async fn control_stream(
         },
     };
 
+    pending.clear();
+
     if let Ok(token) = context.sysdev_token_provider.get().await {
         if context
             .datastore
-            .update_device_online_status(&token.jwt, &device_uuid, false)
+            .update_device_online_status(&token.jwt, &device_uuid, false, false)
             .await
             .is_err()
         {
@@ -54,80 +65,158 @@ pub(crate) async fn control_stream(
     let _ = context.orchestractor.on_disconnected(&device_uuid).await;
 }
 
-async fn healthcheck(stream: OutboundStream) -> Result<(), Error> {
+/// Sends periodic heartbeats and fails once the client stops acknowledging them.
+///
+/// Heartbeats are sent with `try_send`: on a half-open connection the outbound
+/// channel fills up, and a blocking send would never return.
+async fn healthcheck(
+    stream: OutboundStream,
+    health: ConnectionHealth,
+    config: OrchestratorConfig,
+) -> Result<(), Error> {
     loop {
+        if health.is_dead(config.max_missed_heartbeats) {
+            return Err(format!(
+                "Client missed {} consecutive heartbeats",
+                health.missed_heartbeats()
+            ))
+            .handle_err(location!());
+        }
+
         let heartbeat = ServerMessage {
             message: Some(server_message::Message::HeartbeatMessage(())),
+            ..Default::default()
         };
 
-        stream.send(Ok(heartbeat)).await.handle_err(location!())?;
+        match stream.try_send(Ok(heartbeat)) {
+            Ok(()) => health.record_heartbeat_sent(),
+            Err(TrySendError::Full(_)) => health.record_heartbeat_missed(),
+            Err(TrySendError::Closed(_)) => {
+                return Err("Outbound stream closed").handle_err(location!());
+            }
+        }
 
-        tokio::time::sleep(HEARTBEAT_TIME).await;
+        tokio::time::sleep(config.heartbeat_interval).await;
     }
 }
 
+/// Serves the authenticated part of the control stream.
+///
+/// The client first has to authenticate. Once it does, it is issued datastore tokens
+/// until its authorization is revoked, at which point the token provider is dropped and
+/// the client is expected to authenticate again.
 async fn authstream(
     mut inbound: InboundStream,
     outbound: OutboundStream,
+    pending: PendingCommands,
+    health: ConnectionHealth,
+    mut revoked: watch::Receiver<bool>,
     context: AppContext,
 ) -> Result<(), Error> {
-    let message = inbound
-        .message()
-        .await
-        .handle_err(location!())?
-        .ok_or("Client sent an empty message")
-        .handle_err(location!())?
-        .message
-        .ok_or("Malformed message (missing payload)")
-        .handle_err(location!())?;
-
-    let authentication = match message {
-        client_message::Message::Authentication(authentication) => authentication,
-        _ => Err("Unexpected message").handle_err(location!())?,
-    };
+    loop {
+        let authentication = loop {
+            let message = inbound
+                .message()
+                .await
+                .handle_err(location!())?
+                .ok_or("Client sent an empty message")
+                .handle_err(location!())?
+                .message
+                .ok_or("Malformed message (missing payload)")
+                .handle_err(location!())?;
 
-    let token_provider = TokenProvider::new(
-        authentication.app_id,
-        authentication.app_secret,
-        false,
-        context.datastore,
-    );
-
-    outbound
-        .send(Ok(ServerMessage {
-            message: Some(server_message::Message::UpdateTokenCommand(
-                token_provider.get().await?.jwt.clone(),
-            )),
-        }))
-        .await
-        .handle_err(location!())?;
+            health.record_activity();
 
-    loop {
-        tokio::select! {
-            _ = tokio::time::sleep(TOKEN_UPDATE_TIME) => {
-                outbound
-                    .send(Ok(ServerMessage {
-                        message: Some(server_message::Message::UpdateTokenCommand(
-                            token_provider.get().await?.jwt.clone(),
-                        )),
-                    }))
-                    .await
-                    .handle_err(location!())?;
+            // Unauthorized clients stay connected while waiting for approval,
+            // answering heartbeats and commands in the meantime.
+            match message {
+                client_message::Message::Authentication(authentication) => break authentication,
+                client_message::Message::HeartbeatAck(()) => health.record_heartbeat_ack(),
+                client_message::Message::CommandResult(result) => {
+                    pending.resolve(result);
+                }
+                _ => Err("Unexpected message").handle_err(location!())?,
             }
+        };
 
-            msg = inbound.message() => {
-                match msg {
-                    Ok(Some(_)) => {
-                        log::warn!("Unexpected message from client after authentication; ignoring");
-                    }
-                    Ok(None) => {
-                        return Err("Inbound stream closed by client").handle_err(location!());
-                    }
-                    Err(e) => {
-                        return Err(format!("Inbound stream error: {}", e)).handle_err(location!());
+        if *revoked.borrow() {
+            log::warn!("Ignoring authentication of a deauthorized client");
+            continue;
+        }
+
+        let token_provider = TokenProvider::new(
+            authentication.app_id,
+            authentication.app_secret,
+            false,
+            context.datastore.clone(),
+        );
+
+        outbound
+            .send(Ok(ServerMessage {
+                message: Some(server_message::Message::UpdateTokenCommand(
+                    token_provider.get().await?.jwt.clone(),
+                )),
+                ..Default::default()
+            }))
+            .await
+            .handle_err(location!())?;
+
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TOKEN_UPDATE_TIME) => {
+                    outbound
+                        .send(Ok(ServerMessage {
+                            message: Some(server_message::Message::UpdateTokenCommand(
+                                token_provider.get().await?.jwt.clone(),
+                            )),
+                            ..Default::default()
+                        }))
+                        .await
+                        .handle_err(location!())?;
+                }
+
+                res = revoked.wait_for(|revoked| *revoked) => {
+                    res.handle_err(location!())?;
+                    log::info!("Authorization revoked, invalidating the client token");
+                    break;
+                }
+
+                msg = inbound.message() => {
+                    match msg {
+                        Ok(Some(message)) => {
+                            health.record_activity();
+                            handle_client_message(message, &pending, &health);
+                        }
+                        Ok(None) => {
+                            return Err("Inbound stream closed by client").handle_err(location!());
+                        }
+                        Err(e) => {
+                            return Err(format!("Inbound stream error: {}", e)).handle_err(location!());
+                        }
                     }
                 }
             }
         }
     }
 }
+
+fn handle_client_message(
+    message: ClientMessage,
+    pending: &PendingCommands,
+    health: &ConnectionHealth,
+) {
+    match message.message {
+        Some(client_message::Message::HeartbeatAck(())) => {
+            health.record_heartbeat_ack();
+        }
+        Some(client_message::Message::CommandResult(result)) => {
+            let request_id = result.request_id.clone();
+            if !pending.resolve(result) {
+                log::debug!("Nobody is awaiting the result of command {}", request_id);
+            }
+        }
+        _ => {
+            log::warn!("Unexpected message from client after authentication; ignoring");
+        }
+    }
+}
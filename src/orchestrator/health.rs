@@ -0,0 +1,132 @@
+//! Liveness information about a client's control stream.
+//!
+//! The data is shared between the `Client` (which exposes it to the API)
+//! and the control stream task (which updates it).
+//!
+//! Every heartbeat sent to the client is expected to be answered with a `HeartbeatAck`.
+//! A heartbeat still unanswered when the next one is due counts as missed.
+//! Missed heartbeats are only enforced once the client has acknowledged at least one
+//! heartbeat, so agents that predate acknowledgements are not disconnected.
+
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+struct HealthData {
+    last_heartbeat: Option<DateTime<Utc>>,
+    last_seen: Option<DateTime<Utc>>,
+    latency: Option<Duration>,
+    awaiting_ack_since: Option<Instant>,
+    missed_heartbeats: u32,
+    acks_supported: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnectionHealth {
+    data: Arc<Mutex<HealthData>>,
+}
+
+impl ConnectionHealth {
+    /// Records that a heartbeat has just been sent to the client.
+    pub fn record_heartbeat_sent(&self) {
+        let mut data = self.data.lock().unwrap();
+
+        if data.awaiting_ack_since.is_some() {
+            data.missed_heartbeats += 1;
+        }
+
+        data.awaiting_ack_since = Some(Instant::now());
+    }
+
+    /// Records a heartbeat that could not be delivered because the outbound stream is congested.
+    pub fn record_heartbeat_missed(&self) {
+        self.data.lock().unwrap().missed_heartbeats += 1;
+    }
+
+    /// Records the acknowledgement of the last heartbeat.
+    pub fn record_heartbeat_ack(&self) {
+        let mut data = self.data.lock().unwrap();
+
+        if let Some(sent_at) = data.awaiting_ack_since.take() {
+            data.latency = Some(sent_at.elapsed());
+        }
+
+        data.last_heartbeat = Some(Utc::now());
+        data.missed_heartbeats = 0;
+        data.acks_supported = true;
+    }
+
+    /// Records that a message of any kind has been received from the client.
+    pub fn record_activity(&self) {
+        self.data.lock().unwrap().last_seen = Some(Utc::now());
+    }
+
+    /// Returns `true` if the client supports acknowledgements and missed at least `limit`
+    /// consecutive heartbeats.
+    pub fn is_dead(&self, limit: u32) -> bool {
+        let data = self.data.lock().unwrap();
+        data.acks_supported && data.missed_heartbeats >= limit
+    }
+
+    pub fn last_heartbeat(&self) -> Option<DateTime<Utc>> {
+        self.data.lock().unwrap().last_heartbeat
+    }
+
+    pub fn last_seen(&self) -> Option<DateTime<Utc>> {
+        self.data.lock().unwrap().last_seen
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        self.data.lock().unwrap().latency
+    }
+
+    pub fn missed_heartbeats(&self) -> u32 {
+        self.data.lock().unwrap().missed_heartbeats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unanswered_heartbeats_are_counted() {
+        let health = ConnectionHealth::default();
+
+        health.record_heartbeat_sent();
+        health.record_heartbeat_ack();
+
+        health.record_heartbeat_sent();
+        health.record_heartbeat_sent();
+        health.record_heartbeat_sent();
+
+        assert_eq!(health.missed_heartbeats(), 2);
+        assert!(health.is_dead(2));
+        assert!(!health.is_dead(3));
+    }
+
+    #[test]
+    fn test_ack_resets_missed_heartbeats() {
+        let health = ConnectionHealth::default();
+
+        health.record_heartbeat_sent();
+        health.record_heartbeat_sent();
+        health.record_heartbeat_ack();
+
+        assert_eq!(health.missed_heartbeats(), 0);
+        assert!(health.latency().is_some());
+        assert!(health.last_heartbeat().is_some());
+    }
+
+    #[test]
+    fn test_clients_without_acks_are_never_dead() {
+        let health = ConnectionHealth::default();
+
+        for _ in 0..10 {
+            health.record_heartbeat_sent();
+        }
+
+        assert!(!health.is_dead(3));
+    }
+}
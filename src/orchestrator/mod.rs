@@ -1,42 +1,61 @@
 use client::Client;
+pub use client::ClientInfo;
+use config::OrchestratorConfig;
 use nullnet_liberror::{Error, ErrorHandler, Location, location};
-use std::collections::HashMap;
+pub use online_status::{online_status_sweep, reset_online_status};
+pub use pending_commands::{CommandStatus, PendingCommand};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tunnels::OpenTunnels;
+pub use tunnels::TunnelGuard;
 
 use crate::{
     app_context::AppContext,
     orchestrator::{
-        client::{InboundStream, OutboundStream},
+        client::{InboundStream, OutboundStream, PeerCertificates},
         new_connection_handler::NewConnectionHandler,
     },
 };
 
 mod auth_request_handler;
 mod client;
+mod config;
 mod control_stream;
+mod health;
 mod new_connection_handler;
+mod online_status;
+mod pending_commands;
+mod tunnels;
 
 type ClientsMap = Arc<Mutex<HashMap<String, Arc<Mutex<Client>>>>>;
 
 #[derive(Debug, Clone, Default)]
 pub struct Orchestrator {
     pub(crate) clients: ClientsMap,
+    pub(crate) config: OrchestratorConfig,
 }
 
 impl Orchestrator {
     pub fn new() -> Self {
-        Self::default()
+        Self {
+            clients: ClientsMap::default(),
+            config: OrchestratorConfig::from_env(),
+        }
     }
 
     pub fn on_new_connection(
         &self,
         inbound: InboundStream,
         outbound: OutboundStream,
+        remote_addr: Option<SocketAddr>,
+        peer_certs: Option<PeerCertificates>,
         context: AppContext,
     ) {
         log::info!("Orchestrator: on_new_connection");
-        let handler = NewConnectionHandler::new(context);
+        let handler = NewConnectionHandler::new(context, remote_addr, peer_certs);
         tokio::spawn(handler.handle(inbound, outbound));
     }
 
@@ -53,4 +72,32 @@ impl Orchestrator {
     pub async fn get_client(&self, device_uuid: &str) -> Option<Arc<Mutex<Client>>> {
         self.clients.lock().await.get(device_uuid).cloned()
     }
+
+    /// Returns the UUIDs of all devices with an active control stream.
+    pub async fn connected_devices(&self) -> HashSet<String> {
+        self.clients.lock().await.keys().cloned().collect()
+    }
+
+    /// Returns the tunnel registries of all connected clients.
+    pub(crate) async fn open_tunnels(&self) -> Vec<OpenTunnels> {
+        let clients: Vec<_> = self.clients.lock().await.values().cloned().collect();
+
+        let mut tunnels = Vec::with_capacity(clients.len());
+        for client in clients {
+            tunnels.push(client.lock().await.tunnels());
+        }
+
+        tunnels
+    }
+
+    /// Tells every connected client that the server is shutting down.
+    pub async fn notify_shutdown(&self, reconnect_after: Duration) {
+        let clients: Vec<_> = self.clients.lock().await.values().cloned().collect();
+
+        for client in clients {
+            if let Err(err) = client.lock().await.notify_shutdown(reconnect_after) {
+                log::warn!("Failed to notify client about shutdown: {}", err.to_str());
+            }
+        }
+    }
 }
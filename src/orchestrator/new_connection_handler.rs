@@ -12,19 +12,30 @@
 
 use crate::app_context::AppContext;
 use crate::orchestrator::auth_request_handler::AuthReqHandler;
-use crate::orchestrator::client::{InboundStream, OutboundStream};
+use crate::orchestrator::client::{InboundStream, OutboundStream, PeerCertificates};
 use crate::protocol::wallguard_commands::client_message::Message;
+use std::net::SocketAddr;
 use std::time::Duration;
 
 const AUTH_TIMEOUT: Duration = Duration::from_millis(1_000);
 
 pub struct NewConnectionHandler {
     context: AppContext,
+    remote_addr: Option<SocketAddr>,
+    peer_certs: Option<PeerCertificates>,
 }
 
 impl NewConnectionHandler {
-    pub fn new(context: AppContext) -> Self {
-        Self { context }
+    pub fn new(
+        context: AppContext,
+        remote_addr: Option<SocketAddr>,
+        peer_certs: Option<PeerCertificates>,
+    ) -> Self {
+        Self {
+            context,
+            remote_addr,
+            peer_certs,
+        }
     }
 
     pub async fn handle(self, inbound: InboundStream, outbound: OutboundStream) {
@@ -59,7 +70,7 @@ impl NewConnectionHandler {
 
         match inner_msg {
             Message::AuthorizationRequest(auth) => {
-                let handler = AuthReqHandler::new(self.context);
+                let handler = AuthReqHandler::new(self.context, self.remote_addr, self.peer_certs);
                 tokio::spawn(handler.handle(inbound, outbound, auth));
                 Ok(())
             }
@@ -0,0 +1,85 @@
+//! Keeps the `is_device_online` flag in the datastore consistent with the set of
+//! clients actually connected to the orchestrator.
+//!
+//! The flag is normally maintained by the control stream itself, but it goes stale
+//! whenever the server stops without running its cleanup (crash, kill, datastore hiccup).
+//! To recover from that:
+//! - On startup, every device is marked offline, since no client can be connected yet.
+//! - Periodically, devices flagged online without a control stream are marked offline,
+//!   and connected devices flagged offline are marked online.
+//!
+//! Devices of every organization are concerned, so the root account is used throughout.
+
+use crate::app_context::AppContext;
+use nullnet_liberror::Error;
+use std::collections::HashSet;
+use std::time::Duration;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Marks all devices offline. Must run before the control service accepts connections.
+pub async fn reset_online_status(context: &AppContext) -> Result<(), Error> {
+    log::info!("Resetting online status of all devices");
+
+    let token = context.root_token_provider.get().await?;
+
+    context.datastore.mark_all_devices_offline(&token.jwt).await
+}
+
+/// Periodically reconciles the datastore online flags with the connected clients.
+pub async fn online_status_sweep(context: AppContext) {
+    loop {
+        tokio::time::sleep(SWEEP_INTERVAL).await;
+
+        if let Err(err) = sweep(&context).await {
+            log::error!("Online status sweep failed: {}", err.to_str());
+        }
+    }
+}
+
+async fn sweep(context: &AppContext) -> Result<(), Error> {
+    let token = context.root_token_provider.get().await?;
+
+    let connected = context.orchestractor.connected_devices().await;
+
+    let flagged_online: HashSet<String> = context
+        .datastore
+        .obtain_online_devices(&token.jwt)
+        .await?
+        .into_iter()
+        .map(|device| device.uuid)
+        .collect();
+
+    for uuid in flagged_online.difference(&connected) {
+        // The device may have connected since the snapshot was taken.
+        if context.orchestractor.get_client(uuid).await.is_some() {
+            continue;
+        }
+
+        log::info!(
+            "Device {} is flagged online but not connected, fixing",
+            uuid
+        );
+
+        context
+            .datastore
+            .update_device_online_status(&token.jwt, uuid, false, true)
+            .await?;
+    }
+
+    for uuid in connected.difference(&flagged_online) {
+        // The device may have disconnected since the snapshot was taken.
+        if context.orchestractor.get_client(uuid).await.is_none() {
+            continue;
+        }
+
+        log::info!("Device {} is connected but flagged offline, fixing", uuid);
+
+        context
+            .datastore
+            .update_device_online_status(&token.jwt, uuid, true, true)
+            .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+//! Correlates commands sent to a client with the `CommandResult`s it reports back.
+//!
+//! Every command sent through a `Client` is tagged with a freshly generated request id and
+//! registered here. When the control stream receives a `CommandResult` carrying the same id,
+//! the waiting `PendingCommand` is resolved. Entries are removed as soon as the corresponding
+//! `PendingCommand` is dropped, so commands nobody awaits do not accumulate.
+
+use crate::protocol::wallguard_commands::CommandResult;
+use crate::utilities::random::generate_random_string;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Default time a client is given to report the result of a command.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+type ResultsMap = Arc<Mutex<HashMap<String, oneshot::Sender<CommandResult>>>>;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PendingCommands {
+    results: ResultsMap,
+}
+
+impl PendingCommands {
+    /// Registers a new command and returns the handle used to await its result.
+    pub fn register(&self) -> PendingCommand {
+        let request_id = generate_random_string(16);
+        let (tx, rx) = oneshot::channel();
+
+        self.results.lock().unwrap().insert(request_id.clone(), tx);
+
+        PendingCommand {
+            request_id,
+            receiver: rx,
+            commands: self.clone(),
+        }
+    }
+
+    /// Delivers a `CommandResult` to the matching `PendingCommand`.
+    ///
+    /// Returns `false` if no command with the given request id is awaiting a result.
+    pub fn resolve(&self, result: CommandResult) -> bool {
+        let sender = self.results.lock().unwrap().remove(&result.request_id);
+
+        match sender {
+            Some(sender) => sender.send(result).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Drops all outstanding commands, waking up their waiters with an error.
+    pub fn clear(&self) {
+        self.results.lock().unwrap().clear();
+    }
+
+    fn remove(&self, request_id: &str) {
+        self.results.lock().unwrap().remove(request_id);
+    }
+}
+
+/// Result of a command the client didn't reject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStatus {
+    /// The client reported applying the command.
+    Applied,
+    /// The client didn't report on the command in time.
+    Unacknowledged,
+}
+
+impl CommandStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandStatus::Applied => "applied",
+            CommandStatus::Unacknowledged => "unacknowledged",
+        }
+    }
+}
+
+/// Handle to a command that has been sent to a client and whose result may be awaited.
+#[derive(Debug)]
+pub struct PendingCommand {
+    request_id: String,
+    receiver: oneshot::Receiver<CommandResult>,
+    commands: PendingCommands,
+}
+
+impl PendingCommand {
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Waits until the client reports the command result.
+    ///
+    /// A command the client doesn't report on within `COMMAND_TIMEOUT` is `Unacknowledged`:
+    /// clients predating `CommandResult` never report, yet may well have applied it.
+    ///
+    /// # Errors
+    /// Returns an error if the client rejected the command, or the control stream was closed
+    /// before a result arrived.
+    pub async fn wait(self) -> Result<CommandStatus, Error> {
+        self.wait_for(COMMAND_TIMEOUT).await
+    }
+
+    /// Waits until the client reports the command result, for at most `timeout`.
+    ///
+    /// Meant for commands that take the client longer than `COMMAND_TIMEOUT` to apply.
+    pub async fn wait_for(mut self, timeout: Duration) -> Result<CommandStatus, Error> {
+        match tokio::time::timeout(timeout, &mut self.receiver).await {
+            Ok(Ok(result)) if result.success => Ok(CommandStatus::Applied),
+            Ok(Ok(result)) => Err(format!(
+                "Device failed to apply the command: {}",
+                result.error
+            ))
+            .handle_err(location!()),
+            Ok(Err(_)) => {
+                Err("Control stream closed before the device responded").handle_err(location!())
+            }
+            Err(_) => Ok(CommandStatus::Unacknowledged),
+        }
+    }
+}
+
+impl Drop for PendingCommand {
+    fn drop(&mut self) {
+        self.commands.remove(&self.request_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolved_command_succeeds() {
+        let commands = PendingCommands::default();
+        let pending = commands.register();
+
+        assert!(commands.resolve(CommandResult {
+            request_id: pending.request_id().to_string(),
+            success: true,
+            error: String::new(),
+        }));
+
+        assert_eq!(pending.wait().await.ok(), Some(CommandStatus::Applied));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_command_fails() {
+        let commands = PendingCommands::default();
+        let pending = commands.register();
+
+        commands.resolve(CommandResult {
+            request_id: pending.request_id().to_string(),
+            success: false,
+            error: String::from("unsupported"),
+        });
+
+        assert!(pending.wait().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cleared_command_fails() {
+        let commands = PendingCommands::default();
+        let pending = commands.register();
+
+        commands.clear();
+
+        assert!(pending.wait().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_command_times_out() {
+        let commands = PendingCommands::default();
+        let pending = commands.register();
+
+        assert_eq!(
+            pending.wait_for(Duration::from_millis(10)).await.ok(),
+            Some(CommandStatus::Unacknowledged)
+        );
+    }
+
+    #[test]
+    fn test_dropped_command_is_forgotten() {
+        let commands = PendingCommands::default();
+        let request_id = commands.register().request_id().to_string();
+
+        assert!(!commands.resolve(CommandResult {
+            request_id,
+            success: true,
+            error: String::new(),
+        }));
+    }
+}
@@ -0,0 +1,114 @@
+//! Book-keeping of the reverse tunnels currently open to a client.
+//!
+//! Every tunneled channel established through the orchestrator is registered here and
+//! represented by a `TunnelGuard`. The entry lives for as long as the guard does, so the
+//! guard must be kept alive by whoever is using the tunneled stream.
+//!
+//! Tunnels can be closed from the server side with `OpenTunnels::close_all`; users of a
+//! tunnel are expected to stop as soon as `TunnelGuard::closed` resolves.
+
+use crate::datastore::RemoteAccessType;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TunnelInfo {
+    #[serde(rename = "type")]
+    pub r#type: RemoteAccessType,
+    pub opened_at: String,
+}
+
+#[derive(Debug)]
+struct TunnelEntry {
+    r#type: RemoteAccessType,
+    opened_at: DateTime<Utc>,
+    closer: watch::Sender<bool>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OpenTunnels {
+    entries: Arc<Mutex<HashMap<u64, TunnelEntry>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl OpenTunnels {
+    /// Registers a newly opened tunnel of the given type.
+    pub fn open(&self, r#type: RemoteAccessType) -> TunnelGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (closer, closed) = watch::channel(false);
+
+        let entry = TunnelEntry {
+            r#type,
+            opened_at: Utc::now(),
+            closer,
+        };
+
+        self.entries.lock().unwrap().insert(id, entry);
+
+        TunnelGuard {
+            id,
+            closed,
+            tunnels: self.clone(),
+        }
+    }
+
+    /// Requests all currently open tunnels to close.
+    pub fn close_all(&self) {
+        for (_, entry) in self.entries.lock().unwrap().drain() {
+            entry.closer.send_replace(true);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+
+    /// Returns a snapshot of the currently open tunnels.
+    pub fn list(&self) -> Vec<TunnelInfo> {
+        self.entries
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| TunnelInfo {
+                r#type: entry.r#type,
+                opened_at: entry.opened_at.to_rfc3339(),
+            })
+            .collect()
+    }
+}
+
+/// Keeps a tunnel registered in `OpenTunnels` until dropped.
+#[derive(Debug)]
+pub struct TunnelGuard {
+    id: u64,
+    closed: watch::Receiver<bool>,
+    tunnels: OpenTunnels,
+}
+
+impl TunnelGuard {
+    /// Resolves once the server requested the tunnel to be closed.
+    ///
+    /// The returned future does not borrow the guard, so it can outlive it.
+    pub fn closed(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut closed = self.closed.clone();
+
+        async move {
+            let _ = closed.wait_for(|closed| *closed).await;
+        }
+    }
+
+    /// Returns `true` if the server requested the tunnel to be closed.
+    pub fn is_closed(&self) -> bool {
+        *self.closed.borrow()
+    }
+}
+
+impl Drop for TunnelGuard {
+    fn drop(&mut self) {
+        self.tunnels.entries.lock().unwrap().remove(&self.id);
+    }
+}
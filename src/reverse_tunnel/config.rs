@@ -1,34 +1,85 @@
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use rustls::ServerConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct ReverseTunnelConfig {
     pub(crate) addr: SocketAddr,
+    pub(crate) tls: Option<ReverseTunnelTlsConfig>,
+}
+
+/// Locations of the PEM files used to serve the reverse tunnel over TLS.
+#[derive(Debug, Clone)]
+pub struct ReverseTunnelTlsConfig {
+    pub(crate) cert: PathBuf,
+    pub(crate) key: PathBuf,
 }
 
 impl ReverseTunnelConfig {
     /// Constructs a `ReverseTunnelConfig` from the environment variables
-    /// `REVERSE_TUNNEL_HOST` and `REVERSE_TUNNEL_PORT`.
+    /// `REVERSE_TUNNEL_HOST` and `REVERSE_TUNNEL_PORT`, falling back to the default address
+    /// if either is missing or invalid.
     ///
-    /// Falls back to `Default` if either environment variable is missing or invalid.
+    /// TLS is enabled when both `REVERSE_TUNNEL_TLS_CERT` and `REVERSE_TUNNEL_TLS_KEY` are set.
     pub fn from_env() -> Self {
+        let default = Self::default();
+
         let host = std::env::var("REVERSE_TUNNEL_HOST").ok();
         let port = std::env::var("REVERSE_TUNNEL_PORT")
             .ok()
             .and_then(|p| p.parse::<u16>().ok());
 
-        if let (Some(host), Some(port)) = (host, port) {
-            if let Ok(addr) = format!("{}:{}", host, port).parse::<SocketAddr>() {
-                return Self { addr };
+        let addr = match (host, port) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port)
+                .parse::<SocketAddr>()
+                .unwrap_or(default.addr),
+            _ => default.addr,
+        };
+
+        let cert = std::env::var("REVERSE_TUNNEL_TLS_CERT").ok();
+        let key = std::env::var("REVERSE_TUNNEL_TLS_KEY").ok();
+
+        let tls = match (cert, key) {
+            (Some(cert), Some(key)) => Some(ReverseTunnelTlsConfig {
+                cert: cert.into(),
+                key: key.into(),
+            }),
+            _ => {
+                log::warn!("Reverse tunnel TLS is not configured, serving in cleartext");
+                None
             }
-        }
+        };
 
-        Self::default()
+        Self { addr, tls }
     }
 }
 
 impl Default for ReverseTunnelConfig {
     fn default() -> Self {
         let addr = "127.0.0.1:7777".parse().unwrap();
-        Self { addr }
+        Self { addr, tls: None }
+    }
+}
+
+impl ReverseTunnelTlsConfig {
+    /// Reads the configured certificate chain and key and builds the corresponding `ServerConfig`.
+    pub fn load(&self) -> Result<Arc<ServerConfig>, Error> {
+        let certs = CertificateDer::pem_file_iter(&self.cert)
+            .handle_err(location!())?
+            .collect::<Result<Vec<_>, _>>()
+            .handle_err(location!())?;
+
+        let key = PrivateKeyDer::from_pem_file(&self.key).handle_err(location!())?;
+
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .handle_err(location!())?;
+
+        Ok(Arc::new(config))
     }
 }
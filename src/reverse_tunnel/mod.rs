@@ -1,17 +1,34 @@
+use crate::shutdown::ShutdownSignal;
 use config::ReverseTunnelConfig;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 use tokio::sync::oneshot;
+use tokio_rustls::TlsAcceptor;
 use tunnel_token::TokenHash;
 use tunnel_token::TunnelToken;
 
+pub use tunnel_stream::TunnelStream;
+
 mod config;
+mod mux;
+mod tunnel_stream;
 mod tunnel_token;
 
-type ListenersMap = Arc<Mutex<HashMap<TokenHash, oneshot::Sender<TcpStream>>>>;
+/// Sent back to the agent once its token has been matched to an expected connection.
+const TOKEN_ACCEPTED: u8 = 0x01;
+/// Sent back to the agent when its token is unknown or no longer expected.
+const TOKEN_REJECTED: u8 = 0x00;
+
+/// Time given to a new connection to complete the TLS and token handshakes.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type ListenersMap = Arc<Mutex<HashMap<TokenHash, oneshot::Sender<TunnelStream>>>>;
 
 #[derive(Debug, Clone)]
 pub struct ReverseTunnel {
@@ -19,12 +36,13 @@ pub struct ReverseTunnel {
 }
 
 impl ReverseTunnel {
-    /// Creates a new reverse tunnel and starts the background task.
-    pub fn new() -> Self {
+    /// Creates a new reverse tunnel and starts the background task,
+    /// which stops accepting connections once `shutdown` is triggered.
+    pub fn new(shutdown: ShutdownSignal) -> Self {
         let config = ReverseTunnelConfig::from_env();
         let listeners = Arc::new(Mutex::new(HashMap::new()));
 
-        tokio::spawn(tunnel_task(config, listeners.clone()));
+        tokio::spawn(tunnel_task(config, listeners.clone(), shutdown));
 
         Self { listeners }
     }
@@ -33,7 +51,7 @@ impl ReverseTunnel {
     ///
     /// Returns the raw token (to be used by the remote client) and a `Receiver`
     /// that resolves when a client connects using the matching token hash.
-    pub async fn expect_connection(&self) -> (TunnelToken, oneshot::Receiver<TcpStream>) {
+    pub async fn expect_connection(&self) -> (TunnelToken, oneshot::Receiver<TunnelStream>) {
         let token = TunnelToken::generate();
 
         let (tx, rx) = oneshot::channel();
@@ -56,16 +74,35 @@ impl ReverseTunnel {
 /// The main reverse tunnel listener task.
 ///
 /// This function binds a TCP listener to the configured address and continuously accepts
-/// incoming connections. Each new connection is expected to send a 32-byte SHA-256 hash
-/// representing the authentication token. If a matching listener is registered with this
-/// hash, the TCP stream is forwarded to it via a `oneshot::Sender`.
+/// incoming connections, wrapping them in TLS if configured. Each new connection is expected
+/// to send a 32-byte SHA-256 hash representing the authentication token. If a matching listener
+/// is registered with this hash, `TOKEN_ACCEPTED` is sent back and the stream is forwarded to it
+/// via a `oneshot::Sender`. Otherwise `TOKEN_REJECTED` is sent back and the connection is closed.
+///
+/// Connections starting with `mux::PREAMBLE` instead carry many streams, see the `mux` module.
 ///
 /// # Parameters
 /// - `config`: The reverse tunnel configuration containing the address to bind.
 /// - `listeners`: A shared map of expected connection hashes to their corresponding receivers.
-async fn tunnel_task(config: ReverseTunnelConfig, listeners: ListenersMap) {
+/// - `shutdown`: Stops the accept loop once triggered. Tunnels already established keep running.
+async fn tunnel_task(
+    config: ReverseTunnelConfig,
+    listeners: ListenersMap,
+    shutdown: ShutdownSignal,
+) {
     log::info!("Reverse tunnel listening on {}", config.addr);
 
+    let acceptor = match config.tls.as_ref().map(|tls| tls.load()).transpose() {
+        Ok(tls) => tls.map(TlsAcceptor::from),
+        Err(err) => {
+            log::error!(
+                "Failed to load reverse tunnel TLS configuration: {}",
+                err.to_str()
+            );
+            std::process::exit(1);
+        }
+    };
+
     let listener = match TcpListener::bind(config.addr).await {
         Ok(listener) => listener,
         Err(err) => {
@@ -79,10 +116,18 @@ async fn tunnel_task(config: ReverseTunnelConfig, listeners: ListenersMap) {
     };
 
     loop {
-        let (mut stream, _) = match listener.accept().await {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown.triggered() => {
+                log::info!("Reverse tunnel stopped accepting connections");
+                return;
+            }
+        };
+
+        let stream = match accepted {
             Ok((stream, addr)) => {
                 log::debug!("Incoming connection from {}", addr);
-                (stream, addr)
+                stream
             }
             Err(err) => {
                 log::error!("Reverse tunnel failed to accept connection: {}", err);
@@ -91,20 +136,45 @@ async fn tunnel_task(config: ReverseTunnelConfig, listeners: ListenersMap) {
         };
 
         let listeners = listeners.clone();
+        let acceptor = acceptor.clone();
+
         tokio::spawn(async move {
-            let hash = match TokenHash::read_from_stream(&mut stream).await {
-                Ok(hash) => hash,
-                Err(err) => {
-                    log::error!(
-                        "Failed to read token hash from tunnel stream: {}",
-                        err.to_str()
-                    );
-                    return shutdown_stream(stream).await;
+            let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(stream, acceptor));
+
+            let (stream, hash) = match handshake.await {
+                Ok(Ok(result)) => result,
+                Ok(Err(err)) => {
+                    log::error!("Reverse tunnel handshake failed: {}", err.to_str());
+                    return;
+                }
+                Err(_) => {
+                    log::error!("Reverse tunnel handshake timed out");
+                    return;
                 }
             };
 
-            match listeners.lock().await.remove(&hash) {
-                Some(channel) => {
+            if hash == TokenHash::from(mux::PREAMBLE) {
+                log::debug!("Serving a multiplexed tunnel connection");
+
+                if let Ok(stream) = respond(stream, TOKEN_ACCEPTED).await {
+                    mux::serve(stream, listeners).await;
+                }
+
+                return;
+            }
+
+            let channel = listeners.lock().await.remove(&hash);
+
+            match channel {
+                Some(channel) if !channel.is_closed() => {
+                    let stream = match respond(stream, TOKEN_ACCEPTED).await {
+                        Ok(stream) => stream,
+                        Err(err) => {
+                            log::error!("Failed to accept tunnel connection: {}", err.to_str());
+                            return;
+                        }
+                    };
+
                     if let Err(stream) = channel.send(stream) {
                         log::error!(
                             "Reverse tunnel failed to forward TCP stream: receiver dropped"
@@ -113,24 +183,50 @@ async fn tunnel_task(config: ReverseTunnelConfig, listeners: ListenersMap) {
                         shutdown_stream(stream).await
                     }
                 }
-                None => {
+                _ => {
                     log::warn!(
                         "Received tunnel connection with unknown token hash: {:?}",
                         hash
                     );
 
-                    shutdown_stream(stream).await
+                    if let Ok(stream) = respond(stream, TOKEN_REJECTED).await {
+                        shutdown_stream(stream).await
+                    }
                 }
             }
         });
     }
 }
 
-/// Gracefully shuts down the given TCP stream. \
-/// Logs a warning if the shutdown operation fails.
-async fn shutdown_stream(mut stream: TcpStream) {
-    use tokio::io::AsyncWriteExt;
+/// Performs the TLS handshake, if enabled, and reads the token hash sent by the agent.
+async fn handshake(
+    stream: TcpStream,
+    acceptor: Option<TlsAcceptor>,
+) -> Result<(TunnelStream, TokenHash), Error> {
+    let mut stream = match acceptor {
+        Some(acceptor) => {
+            let stream = acceptor.accept(stream).await.handle_err(location!())?;
+            TunnelStream::Tls(Box::new(stream))
+        }
+        None => TunnelStream::Plain(stream),
+    };
+
+    let hash = TokenHash::read_from_stream(&mut stream).await?;
+
+    Ok((stream, hash))
+}
 
+/// Tells the agent whether its token was accepted.
+async fn respond(mut stream: TunnelStream, response: u8) -> Result<TunnelStream, Error> {
+    stream.write_u8(response).await.handle_err(location!())?;
+    stream.flush().await.handle_err(location!())?;
+
+    Ok(stream)
+}
+
+/// Gracefully shuts down the given stream. \
+/// Logs a warning if the shutdown operation fails.
+async fn shutdown_stream(mut stream: TunnelStream) {
     if let Err(e) = stream.shutdown().await {
         log::warn!("Failed to shutdown stream: {}", e);
     }
@@ -0,0 +1,168 @@
+use prost::bytes::Bytes;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Size of a frame header: kind (1 byte), stream id (4 bytes), payload length (4 bytes).
+pub(crate) const HEADER_SIZE: usize = 9;
+
+/// Maximum payload carried by a single frame.
+pub(crate) const MAX_PAYLOAD_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum FrameKind {
+    /// Sent by the agent to open a stream. The payload is the tunnel token hash.
+    Open = 0x01,
+    /// Sent by the server when the token of an `Open` frame was accepted.
+    Accept = 0x02,
+    /// Sent by the server when the token of an `Open` frame was rejected.
+    Reject = 0x03,
+    /// Carries stream data.
+    Data = 0x04,
+    /// Grants the peer additional send credit. The payload is a big-endian `u32`.
+    WindowUpdate = 0x05,
+    /// Signals that the sender will not write to the stream anymore.
+    Close = 0x06,
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = io::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::Open),
+            0x02 => Ok(Self::Accept),
+            0x03 => Ok(Self::Reject),
+            0x04 => Ok(Self::Data),
+            0x05 => Ok(Self::WindowUpdate),
+            0x06 => Ok(Self::Close),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown frame kind {value:#04x}"),
+            )),
+        }
+    }
+}
+
+/// A single frame of the multiplexing protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Frame {
+    pub(crate) kind: FrameKind,
+    pub(crate) stream_id: u32,
+    pub(crate) payload: Bytes,
+}
+
+impl Frame {
+    pub fn new(kind: FrameKind, stream_id: u32, payload: Bytes) -> Self {
+        Self {
+            kind,
+            stream_id,
+            payload,
+        }
+    }
+
+    pub fn control(kind: FrameKind, stream_id: u32) -> Self {
+        Self::new(kind, stream_id, Bytes::new())
+    }
+
+    pub fn window_update(stream_id: u32, credit: u32) -> Self {
+        Self::new(
+            FrameKind::WindowUpdate,
+            stream_id,
+            Bytes::copy_from_slice(&credit.to_be_bytes()),
+        )
+    }
+
+    /// Returns the credit carried by a `WindowUpdate` frame.
+    pub fn credit(&self) -> Option<u32> {
+        let bytes: [u8; 4] = self.payload.as_ref().try_into().ok()?;
+        Some(u32::from_be_bytes(bytes))
+    }
+
+    /// Reads the next frame from the given stream.
+    ///
+    /// # Errors
+    /// Returns an error if reading fails, the frame kind is unknown
+    /// or the payload exceeds `MAX_PAYLOAD_SIZE`.
+    pub async fn read_from<R>(reader: &mut R) -> io::Result<Self>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut header = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut header).await?;
+
+        let kind = FrameKind::try_from(header[0])?;
+        let stream_id = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+        let length = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as usize;
+
+        if length > MAX_PAYLOAD_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Frame payload of {length} bytes exceeds the maximum size"),
+            ));
+        }
+
+        let mut payload = vec![0u8; length];
+        reader.read_exact(&mut payload).await?;
+
+        Ok(Self::new(kind, stream_id, payload.into()))
+    }
+
+    /// Writes the frame to the given stream, without flushing it.
+    pub async fn write_to<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut header = [0u8; HEADER_SIZE];
+        header[0] = self.kind as u8;
+        header[1..5].copy_from_slice(&self.stream_id.to_be_bytes());
+        header[5..9].copy_from_slice(&(self.payload.len() as u32).to_be_bytes());
+
+        writer.write_all(&header).await?;
+        writer.write_all(&self.payload).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_roundtrip() {
+        let frame = Frame::new(FrameKind::Data, 7, Bytes::from_static(b"hello"));
+
+        let mut buffer = Vec::new();
+        frame.write_to(&mut buffer).await.unwrap();
+
+        assert_eq!(buffer.len(), HEADER_SIZE + 5);
+
+        let decoded = Frame::read_from(&mut buffer.as_slice()).await.unwrap();
+        assert_eq!(decoded, frame);
+    }
+
+    #[tokio::test]
+    async fn test_window_update_credit() {
+        let frame = Frame::window_update(3, 65_536);
+
+        let mut buffer = Vec::new();
+        frame.write_to(&mut buffer).await.unwrap();
+
+        let decoded = Frame::read_from(&mut buffer.as_slice()).await.unwrap();
+        assert_eq!(decoded.credit(), Some(65_536));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_is_rejected() {
+        let mut buffer = vec![FrameKind::Data as u8, 0, 0, 0, 1];
+        buffer.extend_from_slice(&((MAX_PAYLOAD_SIZE as u32) + 1).to_be_bytes());
+
+        assert!(Frame::read_from(&mut buffer.as_slice()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_frame_kind_is_rejected() {
+        let buffer = [0xffu8, 0, 0, 0, 1, 0, 0, 0, 0];
+
+        assert!(Frame::read_from(&mut buffer.as_slice()).await.is_err());
+    }
+}
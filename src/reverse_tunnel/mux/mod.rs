@@ -0,0 +1,178 @@
+//! Multiplexing of many logical streams over a single reverse tunnel connection.
+//!
+//! An agent opts into multiplexing by sending `PREAMBLE` instead of a token hash right after
+//! connecting. Once accepted, the connection carries frames (see `frame.rs`) instead of raw bytes:
+//! - The agent opens a stream with an `Open` frame carrying the token hash it would otherwise
+//!   have sent on a dedicated connection, and an agent-chosen stream id.
+//! - The server answers with `Accept` or `Reject`, just like the accept/reject byte.
+//! - Both sides exchange `Data` frames, each side sending at most `INITIAL_WINDOW` bytes
+//!   beyond what the other side acknowledged with `WindowUpdate` frames.
+//! - Each side sends `Close` once it is done writing. Dropping the connection aborts all streams.
+
+use super::tunnel_token::{TOKEN_HASH_SIZE, TokenHash};
+use super::{ListenersMap, TunnelStream};
+use frame::{Frame, FrameKind};
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use stream::{FramesSender, StreamsMap};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter, WriteHalf};
+use tokio::sync::mpsc;
+
+pub use stream::MuxStream;
+
+mod frame;
+mod stream;
+
+/// Sent in place of a token hash to open a multiplexed connection.
+pub(crate) const PREAMBLE: [u8; TOKEN_HASH_SIZE] = *b"wallguard-tunnel-multiplexing-v1";
+
+/// Number of frames queued for the connection writer before senders have to wait.
+///
+/// A full queue also stops the connection reader, so that an agent not reading
+/// its frames can't make the server buffer an unbounded number of replies.
+const FRAMES_QUEUE_SIZE: usize = 64;
+
+/// Serves a multiplexed connection until either side closes it.
+pub(crate) async fn serve<S>(stream: S, listeners: ListenersMap)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (mut reader, writer) = tokio::io::split(stream);
+    let (frames, frames_rx) = mpsc::channel(FRAMES_QUEUE_SIZE);
+    let streams = StreamsMap::default();
+
+    let writer_task = tokio::spawn(write_frames(writer, frames_rx));
+
+    loop {
+        let frame = match Frame::read_from(&mut reader).await {
+            Ok(frame) => frame,
+            Err(err) => {
+                log::info!("Multiplexed tunnel connection closed: {}", err);
+                break;
+            }
+        };
+
+        if let Err(err) = handle_frame(frame, &frames, &streams, &listeners).await {
+            log::error!("Multiplexed tunnel protocol error: {}", err.to_str());
+            break;
+        }
+    }
+
+    writer_task.abort();
+
+    for handle in streams.lock().unwrap().values_mut() {
+        handle.reset();
+    }
+}
+
+async fn handle_frame(
+    frame: Frame,
+    frames: &FramesSender,
+    streams: &StreamsMap,
+    listeners: &ListenersMap,
+) -> Result<(), Error> {
+    let id = frame.stream_id;
+
+    match frame.kind {
+        FrameKind::Open => {
+            let hash: [u8; TOKEN_HASH_SIZE] =
+                frame.payload.as_ref().try_into().handle_err(location!())?;
+
+            let hash = TokenHash::from(hash);
+
+            if streams.lock().unwrap().contains_key(&id) {
+                log::warn!("Agent reused open stream id {}", id);
+                return send(frames, Frame::control(FrameKind::Reject, id)).await;
+            }
+
+            let channel = listeners.lock().await.remove(&hash);
+
+            match channel {
+                Some(channel) if !channel.is_closed() => {
+                    let stream = MuxStream::register(id, frames.clone(), streams.clone());
+
+                    send(frames, Frame::control(FrameKind::Accept, id)).await?;
+
+                    if channel.send(TunnelStream::Mux(stream)).is_err() {
+                        log::error!("Reverse tunnel failed to forward stream: receiver dropped");
+                    }
+                }
+                _ => {
+                    log::warn!("Received tunnel stream with unknown token hash: {:?}", hash);
+
+                    send(frames, Frame::control(FrameKind::Reject, id)).await?;
+                }
+            }
+        }
+        FrameKind::Data => {
+            let exceeded = match streams.lock().unwrap().get_mut(&id) {
+                Some(handle) => {
+                    let exceeded = !handle.receive(frame.payload);
+
+                    if exceeded {
+                        handle.reset();
+                    }
+
+                    exceeded
+                }
+                None => false,
+            };
+
+            if exceeded {
+                log::warn!("Agent exceeded the window of stream {}", id);
+                send(frames, Frame::control(FrameKind::Close, id)).await?;
+            }
+        }
+        FrameKind::WindowUpdate => {
+            let credit = frame
+                .credit()
+                .ok_or("Malformed window update")
+                .handle_err(location!())?;
+
+            if let Some(handle) = streams.lock().unwrap().get(&id) {
+                handle.grant(credit);
+            }
+        }
+        FrameKind::Close => {
+            if let Some(handle) = streams.lock().unwrap().get_mut(&id) {
+                handle.close_inbound();
+            }
+        }
+        FrameKind::Accept | FrameKind::Reject => {
+            return Err("Unexpected frame sent by the agent").handle_err(location!());
+        }
+    }
+
+    Ok(())
+}
+
+async fn send(frames: &FramesSender, frame: Frame) -> Result<(), Error> {
+    frames
+        .send(frame)
+        .await
+        .map_err(|_| "Frame writer is gone")
+        .handle_err(location!())
+}
+
+/// Writes queued frames to the connection, flushing whenever the queue runs empty.
+async fn write_frames<S>(writer: WriteHalf<S>, mut frames: mpsc::Receiver<Frame>)
+where
+    S: AsyncWrite,
+{
+    let mut writer = BufWriter::new(writer);
+
+    while let Some(frame) = frames.recv().await {
+        if let Err(err) = frame.write_to(&mut writer).await {
+            log::error!("Failed to write tunnel frame: {}", err);
+            return;
+        }
+
+        if !frames.is_empty() {
+            continue;
+        }
+
+        if let Err(err) = writer.flush().await {
+            log::error!("Failed to flush tunnel frames: {}", err);
+            return;
+        }
+    }
+}
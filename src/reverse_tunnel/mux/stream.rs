@@ -0,0 +1,432 @@
+use super::frame::{Frame, FrameKind, MAX_PAYLOAD_SIZE};
+use prost::bytes::Bytes;
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc;
+use tokio_util::sync::PollSender;
+
+/// Number of bytes each side may send on a stream before receiving a window update.
+pub(crate) const INITIAL_WINDOW: u32 = 256 * 1024;
+
+pub(crate) type StreamsMap = Arc<Mutex<HashMap<u32, StreamHandle>>>;
+pub(crate) type FramesSender = mpsc::Sender<Frame>;
+
+#[derive(Debug)]
+pub(crate) struct StreamState {
+    /// Bytes we may still send before the peer grants more credit.
+    send_window: u32,
+    /// Bytes the peer may still send before we grant more credit.
+    recv_window: u32,
+    write_waker: Option<Waker>,
+    reset: bool,
+}
+
+/// The session side of a stream.
+#[derive(Debug)]
+pub(crate) struct StreamHandle {
+    /// Set to `None` once the peer closed its side, which makes the stream read EOF.
+    inbound: Option<mpsc::UnboundedSender<Bytes>>,
+    state: Arc<Mutex<StreamState>>,
+}
+
+impl StreamHandle {
+    /// Queues data received from the peer.
+    ///
+    /// Returns `false` if the peer exceeded the receive window.
+    pub fn receive(&mut self, data: Bytes) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(recv_window) = state.recv_window.checked_sub(data.len() as u32) else {
+            return false;
+        };
+
+        state.recv_window = recv_window;
+
+        if let Some(inbound) = &self.inbound {
+            let _ = inbound.send(data);
+        }
+
+        true
+    }
+
+    pub fn grant(&self, credit: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.send_window = state.send_window.saturating_add(credit);
+
+        if let Some(waker) = state.write_waker.take() {
+            waker.wake();
+        }
+    }
+
+    pub fn close_inbound(&mut self) {
+        self.inbound = None;
+    }
+
+    /// Aborts the stream: reads return EOF and writes fail.
+    pub fn reset(&mut self) {
+        self.inbound = None;
+
+        let mut state = self.state.lock().unwrap();
+        state.reset = true;
+
+        if let Some(waker) = state.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A logical stream carried over a multiplexed reverse tunnel connection.
+///
+/// Frames are queued for the connection writer, which holds a bounded number of them:
+/// reads and writes wait for room in the queue when the connection is slow.
+#[derive(Debug)]
+pub struct MuxStream {
+    id: u32,
+    inbound: mpsc::UnboundedReceiver<Bytes>,
+    buffered: Bytes,
+    consumed: u32,
+    state: Arc<Mutex<StreamState>>,
+    /// Queue used by writes, for `Data` and `Close` frames.
+    frames: PollSender<Frame>,
+    /// Queue used by reads, for `WindowUpdate` frames. Kept apart from `frames`
+    /// since reads and writes may wait for room from different tasks.
+    window_updates: PollSender<Frame>,
+    streams: StreamsMap,
+    closed: bool,
+}
+
+impl MuxStream {
+    /// Creates a new stream and registers its handle in `streams`.
+    pub(crate) fn register(id: u32, frames: FramesSender, streams: StreamsMap) -> Self {
+        let (inbound_tx, inbound_rx) = mpsc::unbounded_channel();
+
+        let state = Arc::new(Mutex::new(StreamState {
+            send_window: INITIAL_WINDOW,
+            recv_window: INITIAL_WINDOW,
+            write_waker: None,
+            reset: false,
+        }));
+
+        let handle = StreamHandle {
+            inbound: Some(inbound_tx),
+            state: state.clone(),
+        };
+
+        streams.lock().unwrap().insert(id, handle);
+
+        Self {
+            id,
+            inbound: inbound_rx,
+            buffered: Bytes::new(),
+            consumed: 0,
+            state,
+            frames: PollSender::new(frames.clone()),
+            window_updates: PollSender::new(frames),
+            streams,
+            closed: false,
+        }
+    }
+
+    /// Grants the peer the credit consumed so far, once it is worth a frame.
+    ///
+    /// If the queue is full, the credit is granted by a later read,
+    /// the task being woken up once there is room.
+    fn poll_release_consumed(&mut self, cx: &mut Context<'_>) {
+        if self.consumed < INITIAL_WINDOW / 2 {
+            return;
+        }
+
+        // Retried by the next read if the queue is full. Nothing to do if the connection is gone.
+        let Poll::Ready(Ok(())) = self.window_updates.poll_reserve(cx) else {
+            return;
+        };
+
+        self.state.lock().unwrap().recv_window += self.consumed;
+        let _ = self
+            .window_updates
+            .send_item(Frame::window_update(self.id, self.consumed));
+
+        self.consumed = 0;
+    }
+}
+
+impl AsyncRead for MuxStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            this.poll_release_consumed(cx);
+
+            if !this.buffered.is_empty() {
+                let n = this.buffered.len().min(buf.remaining());
+                buf.put_slice(&this.buffered.split_to(n));
+
+                this.consumed += n as u32;
+                this.poll_release_consumed(cx);
+
+                return Poll::Ready(Ok(()));
+            }
+
+            match this.inbound.poll_recv(cx) {
+                Poll::Ready(Some(data)) => this.buffered = data,
+                // The peer closed the stream or the connection is gone.
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for MuxStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if this.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        {
+            let mut state = this.state.lock().unwrap();
+
+            if state.reset {
+                return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+            }
+
+            if state.send_window == 0 {
+                state.write_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+
+        match this.frames.poll_reserve(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(_)) => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        // Only this stream consumes its send window, which can't have shrunk in the meantime.
+        let mut state = this.state.lock().unwrap();
+
+        let n = buf
+            .len()
+            .min(state.send_window as usize)
+            .min(MAX_PAYLOAD_SIZE);
+
+        state.send_window -= n as u32;
+        drop(state);
+
+        let frame = Frame::new(FrameKind::Data, this.id, Bytes::copy_from_slice(&buf[..n]));
+
+        match this.frames.send_item(frame) {
+            Ok(()) => Poll::Ready(Ok(n)),
+            Err(_) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Frames are handed over to the session writer, which flushes them.
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.closed {
+            return Poll::Ready(Ok(()));
+        }
+
+        match this.frames.poll_reserve(cx) {
+            Poll::Ready(Ok(())) => {
+                let _ = this
+                    .frames
+                    .send_item(Frame::control(FrameKind::Close, this.id));
+            }
+            // The connection is gone, and the stream with it.
+            Poll::Ready(Err(_)) => {}
+            Poll::Pending => return Poll::Pending,
+        }
+
+        this.closed = true;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MuxStream {
+    fn drop(&mut self) {
+        let frames = self.frames.get_ref().filter(|_| !self.closed).cloned();
+
+        if let Some(frames) = frames {
+            send_close(frames, self.id);
+        }
+
+        self.streams.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Tells the peer the stream is closed, waiting for room in the queue
+/// in the background if needed.
+fn send_close(frames: FramesSender, id: u32) {
+    let frame = Frame::control(FrameKind::Close, id);
+
+    if let Err(mpsc::error::TrySendError::Full(frame)) = frames.try_send(frame) {
+        tokio::spawn(async move {
+            let _ = frames.send(frame).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reverse_tunnel::mux::serve;
+    use crate::reverse_tunnel::tunnel_token::TokenHash;
+    use crate::reverse_tunnel::{ListenersMap, TunnelStream};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use tokio::sync::oneshot;
+
+    const TOKEN: [u8; 32] = [7; 32];
+
+    /// Serves a multiplexed connection over an in-memory pipe, expecting a stream for `TOKEN`.
+    fn connect() -> (DuplexStream, oneshot::Receiver<TunnelStream>) {
+        let (agent, server) = tokio::io::duplex(64 * 1024);
+        let (sender, receiver) = oneshot::channel();
+
+        let listeners = ListenersMap::default();
+        listeners
+            .try_lock()
+            .unwrap()
+            .insert(TokenHash::from(TOKEN), sender);
+
+        tokio::spawn(serve(server, listeners));
+
+        (agent, receiver)
+    }
+
+    async fn send(agent: &mut DuplexStream, frame: Frame) {
+        frame.write_to(agent).await.unwrap();
+    }
+
+    async fn next(agent: &mut DuplexStream) -> Frame {
+        Frame::read_from(agent).await.unwrap()
+    }
+
+    async fn open(agent: &mut DuplexStream, id: u32, token: [u8; 32]) -> Frame {
+        send(
+            agent,
+            Frame::new(FrameKind::Open, id, Bytes::copy_from_slice(&token)),
+        )
+        .await;
+        next(agent).await
+    }
+
+    #[tokio::test]
+    async fn test_open_data_close() {
+        let (mut agent, receiver) = connect();
+
+        assert_eq!(
+            open(&mut agent, 1, TOKEN).await,
+            Frame::control(FrameKind::Accept, 1)
+        );
+
+        let mut stream = receiver.await.unwrap();
+
+        send(
+            &mut agent,
+            Frame::new(FrameKind::Data, 1, Bytes::from_static(b"ping")),
+        )
+        .await;
+
+        let mut buffer = [0u8; 4];
+        stream.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"ping");
+
+        stream.write_all(b"pong").await.unwrap();
+        assert_eq!(
+            next(&mut agent).await,
+            Frame::new(FrameKind::Data, 1, Bytes::from_static(b"pong"))
+        );
+
+        send(&mut agent, Frame::control(FrameKind::Close, 1)).await;
+        assert_eq!(stream.read(&mut buffer).await.unwrap(), 0);
+
+        stream.shutdown().await.unwrap();
+        assert_eq!(next(&mut agent).await, Frame::control(FrameKind::Close, 1));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_token_is_rejected() {
+        let (mut agent, _receiver) = connect();
+
+        assert_eq!(
+            open(&mut agent, 1, [1; 32]).await,
+            Frame::control(FrameKind::Reject, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_writes_wait_for_window_update() {
+        let (mut agent, receiver) = connect();
+        open(&mut agent, 1, TOKEN).await;
+
+        let mut stream = receiver.await.unwrap();
+
+        let writer = tokio::spawn(async move {
+            let data = vec![0u8; INITIAL_WINDOW as usize + 1];
+            stream.write_all(&data).await.unwrap();
+        });
+
+        let mut received = 0;
+        while received < INITIAL_WINDOW as usize {
+            received += next(&mut agent).await.payload.len();
+        }
+        assert_eq!(received, INITIAL_WINDOW as usize);
+
+        let blocked = tokio::time::timeout(Duration::from_millis(100), next(&mut agent)).await;
+        assert!(blocked.is_err());
+
+        send(&mut agent, Frame::window_update(1, 1)).await;
+
+        let frame = next(&mut agent).await;
+        assert_eq!(frame.kind, FrameKind::Data);
+        assert_eq!(frame.payload.len(), 1);
+
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_data_beyond_window_resets_stream() {
+        let (mut agent, receiver) = connect();
+        open(&mut agent, 1, TOKEN).await;
+
+        let mut stream = receiver.await.unwrap();
+
+        let chunk = Bytes::from(vec![0u8; MAX_PAYLOAD_SIZE]);
+        for _ in 0..INITIAL_WINDOW as usize / MAX_PAYLOAD_SIZE {
+            send(&mut agent, Frame::new(FrameKind::Data, 1, chunk.clone())).await;
+        }
+
+        send(
+            &mut agent,
+            Frame::new(FrameKind::Data, 1, Bytes::from_static(b"!")),
+        )
+        .await;
+        assert_eq!(next(&mut agent).await, Frame::control(FrameKind::Close, 1));
+
+        let error = stream.write_all(b"pong").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+    }
+}
@@ -0,0 +1,61 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
+
+use super::mux::MuxStream;
+
+/// A reverse tunnel connection, either in cleartext or wrapped in TLS,
+/// or a logical stream carried over a multiplexed connection.
+#[derive(Debug)]
+pub enum TunnelStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+    Mux(MuxStream),
+}
+
+impl AsyncRead for TunnelStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            TunnelStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            TunnelStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+            TunnelStream::Mux(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for TunnelStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            TunnelStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            TunnelStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+            TunnelStream::Mux(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            TunnelStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            TunnelStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+            TunnelStream::Mux(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            TunnelStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            TunnelStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+            TunnelStream::Mux(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use crate::utilities::hash::sha256_digest_bytes;
 use crate::utilities::random::generate_random_string;
 use nullnet_liberror::{Error, ErrorHandler, Location, location};
-use tokio::{io::AsyncReadExt, net::TcpStream};
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 /// The expected size (in bytes) of a SHA-256 token hash.
 pub const TOKEN_HASH_SIZE: usize = 32;
@@ -13,14 +13,17 @@ pub struct TokenHash {
 }
 
 impl TokenHash {
-    /// Reads a 32-byte token hash from the beginning of a TCP stream.
+    /// Reads a 32-byte token hash from the beginning of a stream.
     ///
     /// This function assumes that the first message received on the stream
     /// is a fixed-size SHA-256 hash that can be used to identify the reverse tunnel.
     ///
     /// # Errors
     /// Returns an error if reading from the stream fails or fewer than 32 bytes are received.
-    pub async fn read_from_stream(stream: &mut TcpStream) -> Result<Self, Error> {
+    pub async fn read_from_stream<S>(stream: &mut S) -> Result<Self, Error>
+    where
+        S: AsyncRead + Unpin,
+    {
         let mut hash = TokenHash::default();
 
         stream
@@ -0,0 +1,95 @@
+//! Encoding of recordings in the asciicast v2 format.
+//!
+//! A recording is a header line followed by one line per event, each of them a JSON document.
+//! See <https://docs.asciinema.org/manual/asciicast/v2/>.
+
+use super::RecordingMetadata;
+use serde_json::json;
+
+/// Terminal size recorded when the browser didn't report one.
+pub const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+#[derive(Debug, Clone, Copy)]
+pub enum EventType {
+    Output,
+    Input,
+    Resize,
+}
+
+impl EventType {
+    fn code(&self) -> &'static str {
+        match self {
+            EventType::Output => "o",
+            EventType::Input => "i",
+            EventType::Resize => "r",
+        }
+    }
+}
+
+/// Returns the header line of a recording.
+///
+/// Besides the fields of the format, the header holds the metadata of the session,
+/// which players ignore.
+pub fn header(metadata: &RecordingMetadata, size: Option<(u16, u16)>) -> String {
+    let (width, height) = size.unwrap_or(DEFAULT_SIZE);
+
+    let header = json!({
+        "version": 2,
+        "width": width,
+        "height": height,
+        "timestamp": metadata.started_at.timestamp(),
+        "title": format!("{} session on device {}", metadata.session_type, metadata.device_id),
+        "env": { "TERM": "xterm" },
+        "recording_id": metadata.id,
+        "device_id": metadata.device_id,
+        "user_id": metadata.user_id,
+        "session_type": metadata.session_type,
+    });
+
+    format!("{header}\n")
+}
+
+/// Returns the line of an event that occurred `elapsed` seconds after the recording started.
+pub fn event(elapsed: f64, r#type: EventType, data: &str) -> String {
+    let elapsed = (elapsed * 1_000_000.0).round() / 1_000_000.0;
+    format!("{}\n", json!([elapsed, r#type.code(), data]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn encodes_header() {
+        let metadata = RecordingMetadata {
+            id: "rec".into(),
+            device_id: "dev".into(),
+            user_id: Some("user".into()),
+            session_type: "ssh".into(),
+            started_at: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        };
+
+        let header: serde_json::Value =
+            serde_json::from_str(&header(&metadata, Some((120, 40)))).unwrap();
+
+        assert_eq!(header["version"], 2);
+        assert_eq!(header["width"], 120);
+        assert_eq!(header["height"], 40);
+        assert_eq!(header["timestamp"], 1_700_000_000);
+        assert_eq!(header["device_id"], "dev");
+        assert_eq!(header["user_id"], "user");
+    }
+
+    #[test]
+    fn encodes_events() {
+        assert_eq!(
+            event(1.25, EventType::Output, "ls\r\n"),
+            "[1.25,\"o\",\"ls\\r\\n\"]\n"
+        );
+        assert_eq!(
+            event(2.0, EventType::Resize, "80x24"),
+            "[2.0,\"r\",\"80x24\"]\n"
+        );
+    }
+}
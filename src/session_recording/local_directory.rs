@@ -0,0 +1,63 @@
+use super::{RecordingReader, RecordingSink, RecordingWriter};
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+/// Stores each recording as a `<id>.cast` file in a local directory.
+#[derive(Debug, Clone)]
+pub struct LocalDirectorySink {
+    directory: PathBuf,
+}
+
+impl LocalDirectorySink {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.directory.join(format!("{id}.cast"))
+    }
+}
+
+impl RecordingSink for LocalDirectorySink {
+    fn create(
+        &self,
+        id: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<RecordingWriter, Error>> + Send + '_>> {
+        let path = self.path(id);
+
+        Box::pin(async move {
+            tokio::fs::create_dir_all(&self.directory)
+                .await
+                .handle_err(location!())?;
+
+            let file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .await
+                .handle_err(location!())?;
+
+            Ok(Box::new(file) as RecordingWriter)
+        })
+    }
+
+    fn open(
+        &self,
+        id: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<RecordingReader>, Error>> + Send + '_>> {
+        let path = self.path(id);
+
+        Box::pin(async move {
+            match tokio::fs::File::open(path).await {
+                Ok(file) => Ok(Some(Box::new(file) as RecordingReader)),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err).handle_err(location!()),
+            }
+        })
+    }
+}
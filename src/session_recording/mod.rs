@@ -0,0 +1,237 @@
+//! Recording of SSH and TTY sessions, for compliance and later playback.
+//!
+//! Every byte sent by the device through a terminal gateway is recorded in the asciicast v2
+//! format, see [`asciicast`], and handed to a [`RecordingSink`] storing it.
+//! What the user types is echoed back by the device anyway, except for passwords, so it is
+//! only recorded when explicitly enabled.
+//! Recordings are identified by a random id, listed under the remote access session
+//! they were made in and also returned in the `X-Recording-Id` header of the WebSocket handshake.
+
+use crate::datastore::RemoteAccessType;
+use crate::http_proxy::utilities::terminal::TerminalSize;
+use crate::utilities::random::generate_random_string;
+use crate::utilities::utf8::Utf8Decoder;
+use asciicast::EventType;
+use chrono::{DateTime, Utc};
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::Mutex;
+
+pub use local_directory::LocalDirectorySink;
+
+mod asciicast;
+mod local_directory;
+
+/// Longest time recorded events are buffered before being written out, so that little is lost
+/// if the session is interrupted, and recordings of live sessions stay close to up to date.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+pub type RecordingWriter = Box<dyn AsyncWrite + Send + Unpin>;
+pub type RecordingReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Storage of recordings.
+pub trait RecordingSink: Debug + Send + Sync {
+    /// Creates a new recording with the given id.
+    fn create(
+        &self,
+        id: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<RecordingWriter, Error>> + Send + '_>>;
+
+    /// Opens the recording with the given id, if it exists.
+    fn open(
+        &self,
+        id: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<RecordingReader>, Error>> + Send + '_>>;
+}
+
+/// Metadata stored in the header of a recording.
+#[derive(Debug, Clone)]
+pub struct RecordingMetadata {
+    pub id: String,
+    pub device_id: String,
+    /// Account that opened the session, if known.
+    pub user_id: Option<String>,
+    pub session_type: String,
+    pub started_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionRecorder {
+    sink: Arc<dyn RecordingSink>,
+    /// Whether what the user types is recorded, including the passwords typed at prompts.
+    record_input: bool,
+}
+
+impl SessionRecorder {
+    pub fn new(sink: impl RecordingSink + 'static, record_input: bool) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            record_input,
+        }
+    }
+
+    /// Constructs a `SessionRecorder` storing recordings in the directory
+    /// given by the environment variable `SESSION_RECORDINGS_DIR`.
+    /// Input is recorded as well if `SESSION_RECORDINGS_INPUT` is set to `true`.
+    ///
+    /// Returns `None` if the directory is missing, in which case sessions are not recorded.
+    pub fn from_env() -> Option<Self> {
+        let directory = std::env::var("SESSION_RECORDINGS_DIR").ok()?;
+
+        let record_input = std::env::var("SESSION_RECORDINGS_INPUT")
+            .is_ok_and(|value| value.eq_ignore_ascii_case("true"));
+
+        if record_input {
+            log::warn!("Recording user input, including passwords typed at prompts");
+        }
+
+        Some(Self::new(LocalDirectorySink::new(directory), record_input))
+    }
+
+    /// Starts recording a session on the given device.
+    pub async fn start(
+        &self,
+        device_id: &str,
+        user_id: Option<String>,
+        session_type: RemoteAccessType,
+        size: Option<TerminalSize>,
+    ) -> Result<SessionRecording, Error> {
+        let metadata = RecordingMetadata {
+            id: generate_random_string(32).to_ascii_lowercase(),
+            device_id: device_id.to_owned(),
+            user_id,
+            session_type: session_type.as_str().to_owned(),
+            started_at: Utc::now(),
+        };
+
+        let mut writer = BufWriter::new(self.sink.create(&metadata.id).await?);
+
+        let header = asciicast::header(&metadata, size.map(|size| (size.cols, size.rows)));
+        writer
+            .write_all(header.as_bytes())
+            .await
+            .handle_err(location!())?;
+
+        log::info!(
+            "Recording {} session on device {} as {}",
+            metadata.session_type,
+            metadata.device_id,
+            metadata.id
+        );
+
+        Ok(SessionRecording {
+            id: metadata.id,
+            started: Instant::now(),
+            record_input: self.record_input,
+            state: Arc::new(Mutex::new(RecordingState {
+                writer,
+                flushed: Instant::now(),
+                output: Utf8Decoder::default(),
+                input: Utf8Decoder::default(),
+                failed: false,
+            })),
+        })
+    }
+
+    /// Opens a recording for playback, if it exists.
+    pub async fn open(&self, id: &str) -> Result<Option<RecordingReader>, Error> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Ok(None);
+        }
+
+        self.sink.open(id).await
+    }
+}
+
+struct RecordingState {
+    writer: BufWriter<RecordingWriter>,
+    /// When the writer was last flushed.
+    flushed: Instant,
+    output: Utf8Decoder,
+    input: Utf8Decoder,
+    /// Set once a write failed, after which nothing more is recorded.
+    failed: bool,
+}
+
+/// Recording of a single session. Clones record into the same recording.
+#[derive(Clone)]
+pub struct SessionRecording {
+    id: String,
+    started: Instant,
+    record_input: bool,
+    state: Arc<Mutex<RecordingState>>,
+}
+
+impl SessionRecording {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Records data sent by the device to the terminal.
+    pub async fn output(&self, data: &[u8]) {
+        let mut state = self.state.lock().await;
+        let text = state.output.decode(data);
+        self.record(&mut state, EventType::Output, &text).await;
+    }
+
+    /// Records data typed by the user, if enabled.
+    pub async fn input(&self, data: &[u8]) {
+        if !self.record_input {
+            return;
+        }
+
+        let mut state = self.state.lock().await;
+        let text = state.input.decode(data);
+        self.record(&mut state, EventType::Input, &text).await;
+    }
+
+    pub async fn resize(&self, size: TerminalSize) {
+        let mut state = self.state.lock().await;
+        let text = format!("{}x{}", size.cols, size.rows);
+        self.record(&mut state, EventType::Resize, &text).await;
+    }
+
+    /// Writes out buffered events. Must be called once the session has ended.
+    pub async fn finish(&self) {
+        let mut state = self.state.lock().await;
+
+        if state.failed {
+            return;
+        }
+
+        if let Err(err) = state.writer.shutdown().await {
+            log::error!("Failed to finish recording {}: {}", self.id, err);
+        }
+    }
+
+    async fn record(&self, state: &mut RecordingState, r#type: EventType, data: &str) {
+        if state.failed || data.is_empty() {
+            return;
+        }
+
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let event = asciicast::event(elapsed, r#type, data);
+
+        if let Err(err) = state.writer.write_all(event.as_bytes()).await {
+            log::error!("Failed to write recording {}: {}", self.id, err);
+            state.failed = true;
+            return;
+        }
+
+        if state.flushed.elapsed() < FLUSH_INTERVAL {
+            return;
+        }
+
+        if let Err(err) = state.writer.flush().await {
+            log::error!("Failed to write recording {}: {}", self.id, err);
+            state.failed = true;
+        }
+
+        state.flushed = Instant::now();
+    }
+}
@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct ShutdownConfig {
+    /// Maximum time given to open SSH/TTY/UI tunnels to finish before they are closed.
+    pub(crate) grace_period: Duration,
+    /// Delay after which agents are asked to reconnect.
+    pub(crate) reconnect_after: Duration,
+}
+
+impl ShutdownConfig {
+    /// Constructs a `ShutdownConfig` from the environment variables
+    /// `SHUTDOWN_GRACE_PERIOD_SECS` and `SHUTDOWN_RECONNECT_AFTER_SECS`.
+    ///
+    /// Falls back to the default value of each setting that is missing or invalid.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        let grace_period = std::env::var("SHUTDOWN_GRACE_PERIOD_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(default.grace_period);
+
+        let reconnect_after = std::env::var("SHUTDOWN_RECONNECT_AFTER_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(default.reconnect_after);
+
+        Self {
+            grace_period,
+            reconnect_after,
+        }
+    }
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            grace_period: Duration::from_secs(30),
+            reconnect_after: Duration::from_secs(10),
+        }
+    }
+}
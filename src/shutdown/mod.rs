@@ -0,0 +1,132 @@
+//! Coordinated shutdown of the server.
+//!
+//! On SIGTERM or SIGINT the server:
+//! 1. Stops accepting new control channel, HTTP and reverse tunnel connections.
+//! 2. Tells every connected agent that the server is going away and when to reconnect.
+//! 3. Gives open SSH/TTY/UI tunnels a grace period to finish, then closes the remaining ones.
+//! 4. Marks the devices that are still connected as offline.
+
+use crate::app_context::AppContext;
+use config::ShutdownConfig;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+mod config;
+
+const TUNNELS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Shared flag telling long-running services that the server is shutting down.
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for ShutdownSignal {
+    fn default() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+}
+
+impl ShutdownSignal {
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Resolves once the shutdown has been triggered.
+    pub async fn triggered(&self) {
+        let _ = self
+            .sender
+            .subscribe()
+            .wait_for(|triggered| *triggered)
+            .await;
+    }
+}
+
+/// Waits until the process receives either SIGTERM or SIGINT.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => log::info!("Received SIGTERM"),
+                    _ = tokio::signal::ctrl_c() => log::info!("Received SIGINT"),
+                }
+            }
+            Err(err) => {
+                log::error!("Failed to install SIGTERM handler: {}", err);
+                let _ = tokio::signal::ctrl_c().await;
+                log::info!("Received SIGINT");
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        log::info!("Received SIGINT");
+    }
+}
+
+/// Drains the server before exiting. See the module documentation for the steps involved.
+pub async fn graceful_shutdown(context: &AppContext) {
+    let config = ShutdownConfig::from_env();
+
+    log::info!(
+        "Shutting down, waiting up to {}s for open tunnels",
+        config.grace_period.as_secs()
+    );
+
+    context.shutdown.trigger();
+
+    // Agents are likely to drop their control stream as soon as they are notified,
+    // so the tunnels have to be collected beforehand.
+    let tunnels = context.orchestractor.open_tunnels().await;
+
+    context
+        .orchestractor
+        .notify_shutdown(config.reconnect_after)
+        .await;
+
+    let deadline = Instant::now() + config.grace_period;
+
+    while tunnels.iter().any(|tunnels| !tunnels.is_empty()) && Instant::now() < deadline {
+        tokio::time::sleep(TUNNELS_POLL_INTERVAL).await;
+    }
+
+    for tunnels in &tunnels {
+        if !tunnels.is_empty() {
+            log::warn!("Grace period expired, closing remaining tunnels");
+        }
+
+        tunnels.close_all();
+    }
+
+    mark_connected_devices_offline(context).await;
+
+    log::info!("Shutdown complete");
+}
+
+async fn mark_connected_devices_offline(context: &AppContext) {
+    let Ok(token) = context.sysdev_token_provider.get().await else {
+        log::error!("Failed to obtain system device token");
+        return;
+    };
+
+    for uuid in context.orchestractor.connected_devices().await {
+        if let Err(err) = context
+            .datastore
+            .update_device_online_status(&token.jwt, &uuid, false, false)
+            .await
+        {
+            log::error!("Failed to mark device {} offline: {}", uuid, err.to_str());
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::utilities::random::generate_random_string;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tokio::{fs, process::Command};
+
+/// Issues a client certificate signed by the given CA using `openssl`.
+/// The certificate is valid for client authentication only and carries `common_name` as its subject.
+/// Temporary files are written to `/tmp` and deleted after reading.
+///
+/// Returns the PEM-encoded certificate and private key.
+pub async fn issue_client_certificate(
+    ca_cert: &Path,
+    ca_key: &Path,
+    common_name: &str,
+    validity_days: u32,
+) -> Result<(String, String), Error> {
+    let suffix = generate_random_string(8);
+    let key_path = format!("/tmp/client_{suffix}.key");
+    let csr_path = format!("/tmp/client_{suffix}.csr");
+    let cert_path = format!("/tmp/client_{suffix}.crt");
+    let ext_path = format!("/tmp/client_{suffix}.ext");
+
+    let result = async {
+        fs::write(
+            &ext_path,
+            "basicConstraints=CA:FALSE\nkeyUsage=digitalSignature\nextendedKeyUsage=clientAuth\n",
+        )
+        .await
+        .handle_err(location!())?;
+
+        run_openssl(&[
+            "req",
+            "-new",
+            "-newkey",
+            "ec",
+            "-pkeyopt",
+            "ec_paramgen_curve:prime256v1",
+            "-nodes",
+            "-keyout",
+            &key_path,
+            "-out",
+            &csr_path,
+            "-subj",
+            &format!("/CN={common_name}"),
+        ])
+        .await?;
+
+        run_openssl(&[
+            "x509",
+            "-req",
+            "-in",
+            &csr_path,
+            "-CA",
+            &ca_cert.to_string_lossy(),
+            "-CAkey",
+            &ca_key.to_string_lossy(),
+            "-set_serial",
+            &format!("0x{:016x}", rand::random::<u64>()),
+            "-days",
+            &validity_days.to_string(),
+            "-extfile",
+            &ext_path,
+            "-out",
+            &cert_path,
+        ])
+        .await?;
+
+        let certificate = fs::read_to_string(&cert_path)
+            .await
+            .handle_err(location!())?;
+        let private_key = fs::read_to_string(&key_path)
+            .await
+            .handle_err(location!())?;
+
+        Ok((certificate, private_key))
+    }
+    .await;
+
+    for path in [&key_path, &csr_path, &cert_path, &ext_path] {
+        let _ = fs::remove_file(path).await;
+    }
+
+    result
+}
+
+/// Computes the SHA-256 fingerprint of a DER-encoded certificate as a lowercase hex string.
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Computes the SHA-256 fingerprint of the first certificate in a PEM document.
+pub fn pem_fingerprint(pem: &str) -> Result<String, Error> {
+    let body: String = pem
+        .lines()
+        .skip_while(|line| !line.starts_with("-----BEGIN CERTIFICATE-----"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("-----END CERTIFICATE-----"))
+        .collect();
+
+    if body.is_empty() {
+        return Err("No certificate found in PEM document").handle_err(location!());
+    }
+
+    let der = STANDARD.decode(body.trim()).handle_err(location!())?;
+
+    Ok(fingerprint(&der))
+}
+
+async fn run_openssl(args: &[&str]) -> Result<(), Error> {
+    let output = Command::new("openssl")
+        .args(args)
+        .output()
+        .await
+        .handle_err(location!())?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "openssl {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr)
+        ))
+        .handle_err(location!());
+    }
+
+    Ok(())
+}
@@ -1,4 +1,6 @@
+pub mod certificates;
 pub mod hash;
 pub mod json;
 pub mod random;
 pub mod ssh;
+pub mod utf8;
@@ -1,5 +1,8 @@
 use crate::utilities::random::generate_random_string;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
 use nullnet_liberror::{Error, ErrorHandler, Location, location};
+use sha2::{Digest, Sha256};
 use tokio::{fs, process::Command};
 
 const DEFAULT_EMAIL: &str = "wallgaurd@nullnet.ai";
@@ -50,10 +53,23 @@ pub async fn generate_keypair(
     Ok((public_key, private_key))
 }
 
+/// Computes the fingerprint of an SSH host key blob, in the `SHA256:...` format printed by `ssh-keygen -l`.
+pub fn host_key_fingerprint(key: &[u8]) -> String {
+    format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(key)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_host_key_fingerprint() {
+        assert_eq!(
+            host_key_fingerprint(b""),
+            "SHA256:47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU"
+        );
+    }
+
     #[tokio::test]
     async fn test_generate_keypair_with_no_args() {
         let keypair = generate_keypair(None, None).await;
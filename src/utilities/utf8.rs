@@ -0,0 +1,45 @@
+//! Incremental UTF-8 decoding of byte streams.
+
+/// Decodes a byte stream into UTF-8 text, chunk by chunk.
+///
+/// Terminal streams are split at arbitrary byte boundaries, so a multi-byte character
+/// split across chunks is held back until it is complete. Invalid sequences are
+/// replaced with `U+FFFD`.
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn decode(&mut self, chunk: &[u8]) -> String {
+        self.pending.extend_from_slice(chunk);
+
+        let valid_up_to = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            // `error_len` is `None` when the input ends in the middle of a character.
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+
+        let text = String::from_utf8_lossy(&self.pending[..valid_up_to]).into_owned();
+        self.pending.drain(..valid_up_to);
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_split_characters() {
+        let mut decoder = Utf8Decoder::default();
+        let bytes = "é→".as_bytes();
+
+        assert_eq!(decoder.decode(&bytes[..1]), "");
+        assert_eq!(decoder.decode(&bytes[1..3]), "é");
+        assert_eq!(decoder.decode(&bytes[3..]), "→");
+        assert_eq!(decoder.decode(&[0xff, b'a']), "\u{fffd}a");
+    }
+}